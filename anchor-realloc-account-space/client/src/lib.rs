@@ -0,0 +1,554 @@
+//! Client for `realloc_demo`: PDA derivation, builders for every
+//! instruction, decoders for its accounts, merkle proofs and a prediction of
+//! the rent an `add` will charge.
+//!
+//! The integration tests under `tests/` run the SBF build of the program,
+//! so `cargo test --workspace` fails on their missing `realloc_demo.so`
+//! until `anchor build` has been run. `SBF_OUT_DIR` points them at a build
+//! elsewhere than `target/deploy`.
+
+use anchor_lang::prelude::*;
+use anchor_lang::Discriminator;
+use realloc_demo::directory::{name_seed, MAX_NAME_LEN};
+use realloc_demo::merkle;
+
+pub use realloc_demo::copy::CopyProgress;
+pub use realloc_demo::directory::{Directory, DirectoryEntry};
+pub use realloc_demo::map::{Map, MapEntry};
+pub use realloc_demo::paged::{DataPage, PagedList, PagedSlice};
+pub use realloc_demo::{Data, DataHeader, Entry, ErrorCode, GrowthPolicy, ListMode, ListSlice, ID};
+
+/// Program derived addresses of the `realloc_demo` accounts.
+pub mod pda {
+    use anchor_lang::prelude::Pubkey;
+
+    use crate::ID;
+
+    /// The `Data` list `name` created by `creator`. Stays the same when the
+    /// list changes authority. Panics if `name` is longer than 32 bytes.
+    pub fn data(creator: &Pubkey, name: &str) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"data".as_ref(), creator.as_ref(), name.as_bytes()], &ID)
+    }
+
+    /// The directory of the lists `authority` is the authority of.
+    pub fn directory(authority: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"directory".as_ref(), authority.as_ref()], &ID)
+    }
+
+    pub fn paged_list(authority: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"paged".as_ref(), authority.as_ref()], &ID)
+    }
+
+    pub fn page(list: &Pubkey, index: u32) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[
+                b"page".as_ref(),
+                list.as_ref(),
+                index.to_le_bytes().as_ref(),
+            ],
+            &ID,
+        )
+    }
+
+    pub fn map(authority: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"map".as_ref(), authority.as_ref()], &ID)
+    }
+
+    /// Signer of the self-CPIs that carry events.
+    pub fn event_authority() -> Pubkey {
+        Pubkey::find_program_address(&[b"__event_authority".as_ref()], &ID).0
+    }
+}
+
+/// Builders for every `realloc_demo` instruction. Instructions on a `Data`
+/// list take its address, since it is derived from the creator rather than
+/// the current authority.
+pub mod instruction {
+    use anchor_lang::prelude::*;
+    use anchor_lang::solana_program::instruction::Instruction;
+    use anchor_lang::solana_program::sysvar;
+    use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+    use realloc_demo::{accounts, instruction as ix};
+
+    use crate::{pda, GrowthPolicy, ListMode, ID};
+
+    fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+        Instruction {
+            program_id: ID,
+            accounts: accounts.to_account_metas(None),
+            data: data.data(),
+        }
+    }
+
+    fn add_accounts(data: &Pubkey, signer: &Pubkey, payer: Option<&Pubkey>) -> accounts::Add {
+        accounts::Add {
+            data: *data,
+            signer: *signer,
+            payer: payer.copied(),
+            rent: sysvar::rent::ID,
+            system_program: system_program::ID,
+            event_authority: pda::event_authority(),
+            program: ID,
+        }
+    }
+
+    /// Creates the `Data` list `name` of `signer`.
+    pub fn initialize(
+        signer: &Pubkey,
+        name: &str,
+        growth: GrowthPolicy,
+        mode: ListMode,
+    ) -> Instruction {
+        build(
+            accounts::Initialize {
+                data: pda::data(signer, name).0,
+                directory: pda::directory(signer).0,
+                signer: *signer,
+                system_program: system_program::ID,
+                event_authority: pda::event_authority(),
+                program: ID,
+            },
+            ix::Initialize {
+                name: name.to_string(),
+                growth,
+                mode,
+            },
+        )
+    }
+
+    /// Adds `entry` to `data`. Rent top-ups are paid by `payer` if given,
+    /// by `signer` otherwise.
+    pub fn add(
+        data: &Pubkey,
+        signer: &Pubkey,
+        payer: Option<&Pubkey>,
+        entry: Pubkey,
+        tag: u8,
+        expires_at: Option<i64>,
+    ) -> Instruction {
+        build(
+            add_accounts(data, signer, payer),
+            ix::Add {
+                entry,
+                tag,
+                expires_at,
+            },
+        )
+    }
+
+    pub fn add_many(
+        data: &Pubkey,
+        signer: &Pubkey,
+        payer: Option<&Pubkey>,
+        entries: Vec<Pubkey>,
+        tag: u8,
+        expires_at: Option<i64>,
+    ) -> Instruction {
+        build(
+            add_accounts(data, signer, payer),
+            ix::AddMany {
+                entries,
+                tag,
+                expires_at,
+            },
+        )
+    }
+
+    /// Removes `entry` from `data`, with `proof` from `merkle_proof`.
+    /// `sponsor` is the list's sponsor, which has to be passed while it is
+    /// owed lamports, see `DataHeader::sponsored`; the same goes for the
+    /// other instructions that shrink or close a list.
+    pub fn remove(
+        data: &Pubkey,
+        signer: &Pubkey,
+        sponsor: Option<&Pubkey>,
+        entry: Pubkey,
+        proof: Vec<[u8; 32]>,
+    ) -> Instruction {
+        build(
+            accounts::Remove {
+                data: *data,
+                signer: *signer,
+                sponsor: sponsor.copied(),
+                rent: sysvar::rent::ID,
+                event_authority: pda::event_authority(),
+                program: ID,
+            },
+            ix::Remove { entry, proof },
+        )
+    }
+
+    fn resize_accounts(
+        data: &Pubkey,
+        signer: &Pubkey,
+        sponsor: Option<&Pubkey>,
+    ) -> accounts::Resize {
+        accounts::Resize {
+            data: *data,
+            signer: *signer,
+            sponsor: sponsor.copied(),
+            rent: sysvar::rent::ID,
+            system_program: system_program::ID,
+            event_authority: pda::event_authority(),
+            program: ID,
+        }
+    }
+
+    pub fn reserve(data: &Pubkey, signer: &Pubkey, additional: u32) -> Instruction {
+        build(
+            resize_accounts(data, signer, None),
+            ix::Reserve { additional },
+        )
+    }
+
+    pub fn shrink_to_fit(data: &Pubkey, signer: &Pubkey, sponsor: Option<&Pubkey>) -> Instruction {
+        build(resize_accounts(data, signer, sponsor), ix::ShrinkToFit {})
+    }
+
+    /// Closes `data`, whose authority is `signer`.
+    pub fn close(
+        data: &Pubkey,
+        signer: &Pubkey,
+        sponsor: Option<&Pubkey>,
+        recipient: &Pubkey,
+    ) -> Instruction {
+        build(
+            accounts::Close {
+                data: *data,
+                directory: pda::directory(signer).0,
+                signer: *signer,
+                recipient: *recipient,
+                sponsor: sponsor.copied(),
+                event_authority: pda::event_authority(),
+                program: ID,
+            },
+            ix::Close {},
+        )
+    }
+
+    fn configure_accounts(data: &Pubkey, signer: &Pubkey) -> accounts::Configure {
+        accounts::Configure {
+            data: *data,
+            signer: *signer,
+            event_authority: pda::event_authority(),
+            program: ID,
+        }
+    }
+
+    pub fn set_prune_reward(data: &Pubkey, signer: &Pubkey, prune_reward_bps: u16) -> Instruction {
+        build(
+            configure_accounts(data, signer),
+            ix::SetPruneReward { prune_reward_bps },
+        )
+    }
+
+    /// Prunes the first `proofs.len()` expired entries of `data`, whose
+    /// `authority` receives the reclaimed rent minus the reward paid to
+    /// `caller`. Each proof is taken after emptying the leaves of the
+    /// entries before it.
+    pub fn prune_expired(
+        data: &Pubkey,
+        authority: &Pubkey,
+        sponsor: Option<&Pubkey>,
+        caller: &Pubkey,
+        proofs: Vec<Vec<[u8; 32]>>,
+    ) -> Instruction {
+        build(
+            accounts::Prune {
+                data: *data,
+                authority: *authority,
+                caller: *caller,
+                sponsor: sponsor.copied(),
+                rent: sysvar::rent::ID,
+                event_authority: pda::event_authority(),
+                program: ID,
+            },
+            ix::PruneExpired { proofs },
+        )
+    }
+
+    pub fn transfer_authority(
+        data: &Pubkey,
+        signer: &Pubkey,
+        new_authority: Pubkey,
+    ) -> Instruction {
+        build(
+            configure_accounts(data, signer),
+            ix::TransferAuthority { new_authority },
+        )
+    }
+
+    /// Makes `signer` the authority of `data` in place of `authority`.
+    pub fn accept_authority(data: &Pubkey, authority: &Pubkey, signer: &Pubkey) -> Instruction {
+        build(
+            accounts::AcceptAuthority {
+                data: *data,
+                previous_directory: pda::directory(authority).0,
+                previous_authority: *authority,
+                directory: pda::directory(signer).0,
+                signer: *signer,
+                system_program: system_program::ID,
+                event_authority: pda::event_authority(),
+                program: ID,
+            },
+            ix::AcceptAuthority {},
+        )
+    }
+
+    pub fn seal(data: &Pubkey, signer: &Pubkey) -> Instruction {
+        build(configure_accounts(data, signer), ix::Seal {})
+    }
+
+    pub fn contains(data: &Pubkey, entry: Pubkey) -> Instruction {
+        build(accounts::Query { data: *data }, ix::Contains { entry })
+    }
+
+    pub fn list(data: &Pubkey, offset: u32, limit: u16) -> Instruction {
+        build(accounts::List { data: *data }, ix::List { offset, limit })
+    }
+
+    pub fn is_member(data: &Pubkey, entry: Pubkey) -> Instruction {
+        build(accounts::IsMember { data: *data }, ix::IsMember { entry })
+    }
+
+    pub fn verify_membership(data: &Pubkey, entry: Pubkey, proof: Vec<[u8; 32]>) -> Instruction {
+        build(
+            accounts::Verify { data: *data },
+            ix::VerifyMembership { entry, proof },
+        )
+    }
+
+    fn copy_accounts(
+        data: &Pubkey,
+        source: &Pubkey,
+        signer: &Pubkey,
+        payer: Option<&Pubkey>,
+    ) -> accounts::CopyEntries {
+        accounts::CopyEntries {
+            data: *data,
+            source: *source,
+            signer: *signer,
+            payer: payer.copied(),
+            rent: sysvar::rent::ID,
+            system_program: system_program::ID,
+            event_authority: pda::event_authority(),
+            program: ID,
+        }
+    }
+
+    /// Copies a chunk of `source` into `data`, starting at `cursor`. The
+    /// instruction returns the cursor of the next chunk, if any.
+    pub fn copy_from(
+        data: &Pubkey,
+        source: &Pubkey,
+        signer: &Pubkey,
+        payer: Option<&Pubkey>,
+        cursor: u32,
+        limit: u16,
+    ) -> Instruction {
+        build(
+            copy_accounts(data, source, signer, payer),
+            ix::CopyFrom { cursor, limit },
+        )
+    }
+
+    pub fn merge_from(
+        data: &Pubkey,
+        source: &Pubkey,
+        signer: &Pubkey,
+        payer: Option<&Pubkey>,
+        cursor: u32,
+        limit: u16,
+    ) -> Instruction {
+        build(
+            copy_accounts(data, source, signer, payer),
+            ix::MergeFrom { cursor, limit },
+        )
+    }
+
+    /// Migrates the unnamed `Data` list created by `signer` from an older
+    /// layout.
+    pub fn migrate(signer: &Pubkey) -> Instruction {
+        build(
+            accounts::Migrate {
+                data: pda::data(signer, "").0,
+                directory: pda::directory(signer).0,
+                signer: *signer,
+                system_program: system_program::ID,
+                event_authority: pda::event_authority(),
+                program: ID,
+            },
+            ix::Migrate {},
+        )
+    }
+
+    pub fn initialize_paged(signer: &Pubkey, page_capacity: u16) -> Instruction {
+        build(
+            accounts::InitializePaged {
+                list: pda::paged_list(signer).0,
+                signer: *signer,
+                system_program: system_program::ID,
+            },
+            ix::InitializePaged { page_capacity },
+        )
+    }
+
+    /// Adds `entry` to the paged list of `signer`, whose `tail_page` is
+    /// `page_index`.
+    pub fn add_paged(signer: &Pubkey, page_index: u32, entry: Pubkey) -> Instruction {
+        let list = pda::paged_list(signer).0;
+        build(
+            accounts::AddPaged {
+                list,
+                page: pda::page(&list, page_index).0,
+                signer: *signer,
+                system_program: system_program::ID,
+                event_authority: pda::event_authority(),
+                program: ID,
+            },
+            ix::AddPaged { entry },
+        )
+    }
+
+    /// Reads up to `limit` entries of the paged list `list` from the
+    /// `offset`th on. `pages` are the pages holding them, in order.
+    pub fn list_paged(list: &Pubkey, pages: &[Pubkey], offset: u64, limit: u16) -> Instruction {
+        let mut instruction = build(
+            accounts::ListPaged { list: *list },
+            ix::ListPaged { offset, limit },
+        );
+        instruction.accounts.extend(
+            pages
+                .iter()
+                .map(|page| AccountMeta::new_readonly(*page, false)),
+        );
+        instruction
+    }
+
+    pub fn initialize_map(signer: &Pubkey, growth: GrowthPolicy) -> Instruction {
+        build(
+            accounts::InitializeMap {
+                map: pda::map(signer).0,
+                signer: *signer,
+                system_program: system_program::ID,
+            },
+            ix::InitializeMap { growth },
+        )
+    }
+
+    fn update_map_accounts(signer: &Pubkey) -> accounts::UpdateMap {
+        accounts::UpdateMap {
+            map: pda::map(signer).0,
+            signer: *signer,
+            system_program: system_program::ID,
+            event_authority: pda::event_authority(),
+            program: ID,
+        }
+    }
+
+    pub fn upsert(signer: &Pubkey, key: Pubkey, value: u64) -> Instruction {
+        build(update_map_accounts(signer), ix::Upsert { key, value })
+    }
+
+    pub fn increment(signer: &Pubkey, key: Pubkey, amount: u64) -> Instruction {
+        build(update_map_accounts(signer), ix::Increment { key, amount })
+    }
+
+    pub fn delete(signer: &Pubkey, key: Pubkey) -> Instruction {
+        build(update_map_accounts(signer), ix::Delete { key })
+    }
+
+    pub fn get(map: &Pubkey, key: Pubkey) -> Instruction {
+        build(accounts::GetMap { map: *map }, ix::Get { key })
+    }
+}
+
+/// Decodes a `Data` account in the current layout.
+pub fn decode_data(account_data: &[u8]) -> Result<Data> {
+    let data = Data::try_deserialize(&mut &account_data[..])?;
+    require_eq!(
+        data.header.version,
+        Data::VERSION,
+        ErrorCode::UnsupportedVersion
+    );
+    Ok(data)
+}
+
+/// Decodes only the fields of a `Data` account in front of the list.
+pub fn decode_data_header(account_data: &[u8]) -> Result<DataHeader> {
+    require!(
+        account_data.len() >= Data::INIT_SIZE && account_data[..8] == Data::DISCRIMINATOR,
+        anchor_lang::error::ErrorCode::AccountDiscriminatorMismatch
+    );
+    let header = DataHeader::deserialize(&mut &account_data[8..])?;
+    require_eq!(header.version, Data::VERSION, ErrorCode::UnsupportedVersion);
+    Ok(header)
+}
+
+/// The entries of `data` in list order, oldest first in ring mode.
+pub fn entries(data: &Data) -> Vec<Entry> {
+    (0..data.list.len()).map(|i| data.get(i)).collect()
+}
+
+/// The leaves of the merkle tree of `data`, see `realloc_demo::merkle`.
+/// Keys evicted from a full ring keep their leaf but aren't in the account
+/// any more: those leaves are left empty here, and have to be filled in
+/// from the `EntryAdded` events of the keys.
+pub fn merkle_leaves(data: &Data) -> Vec<[u8; 32]> {
+    let mut leaves = vec![[0; 32]; data.header.merkle_len as usize];
+    for entry in entries(data) {
+        leaves[entry.leaf as usize] = merkle::leaf(&entry.key);
+    }
+    leaves
+}
+
+/// The proof of leaf `index` among `leaves`, for `verify_membership`,
+/// `remove` and `prune_expired`.
+pub fn merkle_proof(leaves: &[[u8; 32]], mut index: usize) -> Vec<[u8; 32]> {
+    let mut proof = Vec::with_capacity(merkle::DEPTH);
+    let mut level = leaves.to_vec();
+    let mut empty = [0; 32];
+    for _ in 0..merkle::DEPTH {
+        proof.push(level.get(index ^ 1).copied().unwrap_or(empty));
+        level = level
+            .chunks(2)
+            .map(|pair| merkle::node(&pair[0], pair.get(1).unwrap_or(&empty)))
+            .collect();
+        empty = merkle::node(&empty, &empty);
+        index /= 2;
+    }
+    proof
+}
+
+pub fn decode_directory(account_data: &[u8]) -> Result<Directory> {
+    Directory::try_deserialize(&mut &account_data[..])
+}
+
+/// A list name as stored in `Data` and `Directory` accounts.
+pub fn decode_name(name: &[u8; MAX_NAME_LEN]) -> String {
+    String::from_utf8_lossy(name_seed(name)).into_owned()
+}
+
+pub fn decode_map(account_data: &[u8]) -> Result<Map> {
+    Map::try_deserialize(&mut &account_data[..])
+}
+
+pub fn decode_paged_list(account_data: &[u8]) -> Result<PagedList> {
+    PagedList::try_deserialize(&mut &account_data[..])
+}
+
+pub fn decode_page(account_data: &[u8]) -> Result<DataPage> {
+    DataPage::try_deserialize(&mut &account_data[..])
+}
+
+/// Lamports an `add` to the `Data` account holding `account_data` and
+/// `lamports` will charge its rent payer under `rent`, 0 if the account
+/// already has room.
+pub fn predict_add_top_up(account_data: &[u8], lamports: u64, rent: &Rent) -> Result<u64> {
+    let header = decode_data_header(account_data)?;
+    Ok(header
+        .grown_size_for_add(account_data.len())
+        .map_or(0, |size| {
+            rent.minimum_balance(size).max(1).saturating_sub(lamports)
+        }))
+}
@@ -0,0 +1,74 @@
+//! Running the built `realloc_demo` program in-process, without a
+//! validator. Build it with `anchor build` first; the tests look for it in
+//! `target/deploy` unless `SBF_OUT_DIR` says otherwise.
+
+use std::path::PathBuf;
+use std::sync::Once;
+
+use anchor_lang::prelude::Pubkey;
+use realloc_demo_client::ID;
+use solana_program_test::{BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::account::Account;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::system_program;
+use solana_sdk::transaction::Transaction;
+
+/// Points `ProgramTest` at the build output, once per test binary: tests
+/// run on parallel threads, and setting the environment while another one
+/// reads it is a data race.
+static SBF_OUT_DIR: Once = Once::new();
+
+/// Loads the SBF build of the program rather than linking it natively, so
+/// that the tests run under the real compute, heap and realloc limits.
+pub fn program_test() -> ProgramTest {
+    SBF_OUT_DIR.call_once(|| {
+        let out_dir = std::env::var_os("SBF_OUT_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../target/deploy"));
+        std::env::set_var("SBF_OUT_DIR", out_dir);
+    });
+    let program = PathBuf::from(std::env::var_os("SBF_OUT_DIR").unwrap()).join("realloc_demo.so");
+    assert!(
+        program.exists(),
+        "{} not found, run `anchor build` first",
+        program.display()
+    );
+
+    let mut program_test = ProgramTest::default();
+    program_test.prefer_bpf(true);
+    program_test.add_program("realloc_demo", ID, None);
+    program_test
+}
+
+pub fn system_account(lamports: u64) -> Account {
+    Account::new(lamports, 0, &system_program::ID)
+}
+
+/// Sends `instructions` in one transaction, with fees paid by the context
+/// payer.
+pub async fn send(
+    context: &mut ProgramTestContext,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> Result<(), BanksClientError> {
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+    let blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
+    let transaction = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&context.payer.pubkey()),
+        &all_signers,
+        blockhash,
+    );
+    context.banks_client.process_transaction(transaction).await
+}
+
+pub async fn account(context: &mut ProgramTestContext, address: &Pubkey) -> Account {
+    context
+        .banks_client
+        .get_account(*address)
+        .await
+        .unwrap()
+        .unwrap()
+}
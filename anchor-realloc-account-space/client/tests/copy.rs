@@ -0,0 +1,429 @@
+//! In-process tests of copying and merging lists in chunks.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::AnchorDeserialize;
+use realloc_demo::copy::{MAX_COPY_ENTRIES, MAX_MERGE_COMPARES};
+use realloc_demo::merkle::MerkleTree;
+use realloc_demo_client::{
+    decode_data, entries, instruction, merkle_leaves, merkle_proof, pda, CopyProgress, Data, Entry,
+    ErrorCode, GrowthPolicy, ListMode,
+};
+use solana_program_test::{BanksClientError, ProgramTestContext};
+use solana_sdk::instruction::{Instruction, InstructionError};
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::{Transaction, TransactionError};
+
+mod common;
+
+use common::{account, program_test, send, system_account};
+
+/// Sends `instruction` and decodes the cursor it returns.
+async fn send_for_cursor(
+    context: &mut ProgramTestContext,
+    instruction: Instruction,
+    signer: &Keypair,
+) -> Result<Option<u32>, BanksClientError> {
+    let blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
+    let transaction = Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, signer],
+        blockhash,
+    );
+    let result = context
+        .banks_client
+        .process_transaction_with_metadata(transaction)
+        .await?;
+    result.result?;
+
+    let return_data = result.metadata.unwrap().return_data.unwrap();
+    Ok(Option::<u32>::try_from_slice(&return_data.data).unwrap())
+}
+
+/// Copies or merges all of `source` into `data` in chunks of `limit`,
+/// returning the cursor every chunk handed back.
+async fn copy_all(
+    context: &mut ProgramTestContext,
+    owner: &Keypair,
+    data: &Pubkey,
+    source: &Pubkey,
+    limit: u16,
+    merge: bool,
+) -> Vec<Option<u32>> {
+    let build = if merge {
+        instruction::merge_from
+    } else {
+        instruction::copy_from
+    };
+
+    let mut cursors = Vec::new();
+    let mut cursor = Some(0);
+    while let Some(from) = cursor {
+        let instruction = build(data, source, &owner.pubkey(), None, from, limit);
+        cursor = send_for_cursor(context, instruction, owner).await.unwrap();
+        cursors.push(cursor);
+    }
+    cursors
+}
+
+/// The cursors `copy_all` sees reading `len` entries `chunk` at a time.
+fn chunk_cursors(chunk: usize, len: usize) -> Vec<Option<u32>> {
+    (chunk..len)
+        .step_by(chunk)
+        .map(|cursor| Some(cursor as u32))
+        .chain([None])
+        .collect()
+}
+
+async fn initialize(
+    context: &mut ProgramTestContext,
+    owner: &Keypair,
+    name: &str,
+    mode: ListMode,
+) -> Pubkey {
+    send(
+        context,
+        &[instruction::initialize(
+            &owner.pubkey(),
+            name,
+            GrowthPolicy::ExactFit,
+            mode,
+        )],
+        &[owner],
+    )
+    .await
+    .unwrap();
+    pda::data(&owner.pubkey(), name).0
+}
+
+async fn list(context: &mut ProgramTestContext, data: &Pubkey) -> (Data, usize) {
+    let account = account(context, data).await;
+    (decode_data(&account.data).unwrap(), account.data.len())
+}
+
+#[tokio::test]
+async fn copies_and_merges_a_list_over_several_transactions() {
+    let owner = Keypair::new();
+    let mut program_test = program_test();
+    program_test.add_account(owner.pubkey(), system_account(10_000_000_000));
+    let mut context = program_test.start_with_context().await;
+
+    let source = initialize(&mut context, &owner, "source", ListMode::Append).await;
+    let keys = (0..300).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+    for chunk in keys.chunks(25) {
+        send(
+            &mut context,
+            &[instruction::add_many(
+                &source,
+                &owner.pubkey(),
+                None,
+                chunk.to_vec(),
+                7,
+                None,
+            )],
+            &[&owner],
+        )
+        .await
+        .unwrap();
+    }
+    let (source_list, _) = list(&mut context, &source).await;
+
+    // more than fit in one chunk are read per call
+    let fork = initialize(&mut context, &owner, "fork", ListMode::Append).await;
+    let cursors = copy_all(&mut context, &owner, &fork, &source, u16::MAX, false).await;
+    assert_eq!(cursors, chunk_cursors(MAX_COPY_ENTRIES, 300));
+    assert_eq!(
+        list(&mut context, &fork).await.0.header.copy,
+        CopyProgress::default()
+    );
+
+    let (fork_list, size) = list(&mut context, &fork).await;
+    assert_eq!(entries(&fork_list), entries(&source_list));
+    assert_eq!(fork_list.header.merkle, source_list.header.merkle);
+    assert_eq!(size, Data::INIT_SIZE + 300 * Entry::SIZE);
+
+    // copying again from the start replaces what the fork has since gained
+    send(
+        &mut context,
+        &[instruction::add(
+            &fork,
+            &owner.pubkey(),
+            None,
+            Pubkey::new_unique(),
+            0,
+            None,
+        )],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+    copy_all(&mut context, &owner, &fork, &source, 200, false).await;
+    let (fork_list, _) = list(&mut context, &fork).await;
+    assert_eq!(entries(&fork_list), entries(&source_list));
+
+    // merging into a long list reads fewer entries per call, so that the
+    // lookups in it stay bounded
+    let cursors = copy_all(&mut context, &owner, &fork, &source, 100, true).await;
+    assert_eq!(cursors, chunk_cursors(MAX_MERGE_COMPARES / 300, 300));
+    let (fork_list, _) = list(&mut context, &fork).await;
+    assert_eq!(entries(&fork_list), entries(&source_list));
+
+    // merging keeps the set sorted and skips keys it already has
+    let set = initialize(&mut context, &owner, "set", ListMode::Set).await;
+    let own = Pubkey::new_unique();
+    send(
+        &mut context,
+        &[instruction::add_many(
+            &set,
+            &owner.pubkey(),
+            None,
+            vec![own, keys[42]],
+            0,
+            None,
+        )],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+    // lookups in a set are binary searches, which the budget doesn't cover
+    let cursors = copy_all(&mut context, &owner, &set, &source, 100, true).await;
+    assert_eq!(cursors, chunk_cursors(MAX_COPY_ENTRIES, 300));
+
+    let (set_list, size) = list(&mut context, &set).await;
+    let mut expected = keys.clone();
+    expected.push(own);
+    expected.sort();
+    let set_keys = entries(&set_list)
+        .iter()
+        .map(|entry| entry.key)
+        .collect::<Vec<_>>();
+    assert_eq!(set_keys, expected);
+    assert_eq!(size, Data::INIT_SIZE + 301 * Entry::SIZE);
+    // copied entries keep their tag, the one added directly keeps its own
+    let tag_of = |key: &Pubkey| set_list.list.iter().find(|e| e.key == *key).unwrap().tag;
+    assert_eq!(tag_of(&keys[42]), 0);
+    assert_eq!(tag_of(&keys[43]), 7);
+
+    // every key took the next leaf as it was added, whatever its place in
+    // the set
+    let mut added = vec![own, keys[42]];
+    added.extend(keys.iter().filter(|key| **key != keys[42]));
+    assert_eq!(set_list.header.merkle_len, 301);
+    assert_eq!(set_list.header.merkle, MerkleTree::build(&added));
+}
+
+#[tokio::test]
+async fn rejects_copying_a_list_into_itself() {
+    let owner = Keypair::new();
+    let mut program_test = program_test();
+    program_test.add_account(owner.pubkey(), system_account(1_000_000_000));
+    let mut context = program_test.start_with_context().await;
+
+    let data = initialize(&mut context, &owner, "", ListMode::Append).await;
+    let err = send_for_cursor(
+        &mut context,
+        instruction::copy_from(&data, &data, &owner.pubkey(), None, 0, 10),
+        &owner,
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(
+        err.unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(ErrorCode::SameList.into()))
+    );
+}
+
+#[tokio::test]
+async fn rejects_chunks_that_dont_continue_the_copy() {
+    let owner = Keypair::new();
+    let mut program_test = program_test();
+    program_test.add_account(owner.pubkey(), system_account(1_000_000_000));
+    let mut context = program_test.start_with_context().await;
+
+    let data = initialize(&mut context, &owner, "", ListMode::Append).await;
+    let source = initialize(&mut context, &owner, "source", ListMode::Append).await;
+    let other = initialize(&mut context, &owner, "other", ListMode::Append).await;
+    let keys = (0..4).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+    for list in [source, other] {
+        send(
+            &mut context,
+            &[instruction::add_many(
+                &list,
+                &owner.pubkey(),
+                None,
+                keys.clone(),
+                0,
+                None,
+            )],
+            &[&owner],
+        )
+        .await
+        .unwrap();
+    }
+    let error = |code: ErrorCode| {
+        TransactionError::InstructionError(0, InstructionError::Custom(code.into()))
+    };
+
+    // a live list can be copied from, and the copy remembers where it is
+    let cursor = send_for_cursor(
+        &mut context,
+        instruction::copy_from(&data, &source, &owner.pubkey(), None, 0, 2),
+        &owner,
+    )
+    .await
+    .unwrap();
+    assert_eq!(cursor, Some(2));
+    let (source_list, _) = list(&mut context, &source).await;
+    assert_eq!(
+        list(&mut context, &data).await.0.header.copy,
+        CopyProgress {
+            source,
+            cursor: 2,
+            source_count: 4,
+            source_root: source_list.header.merkle.root,
+        }
+    );
+
+    // the next chunk has to come from the same source, at the same cursor
+    for (from, cursor) in [(other, 2), (source, 1), (source, 3)] {
+        let err = send_for_cursor(
+            &mut context,
+            instruction::copy_from(&data, &from, &owner.pubkey(), None, cursor, 2),
+            &owner,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.unwrap(), error(ErrorCode::CursorMismatch));
+    }
+
+    // and from a source that hasn't changed since, even if it still holds
+    // as many entries
+    send(
+        &mut context,
+        &[
+            instruction::remove(
+                &source,
+                &owner.pubkey(),
+                None,
+                keys[0],
+                merkle_proof(&merkle_leaves(&source_list), 0),
+            ),
+            instruction::add(&source, &owner.pubkey(), None, keys[0], 0, None),
+        ],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+    assert_eq!(list(&mut context, &source).await.0.header.count, 4);
+    let err = send_for_cursor(
+        &mut context,
+        instruction::merge_from(&data, &source, &owner.pubkey(), None, 2, 2),
+        &owner,
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(err.unwrap(), error(ErrorCode::SourceChanged));
+
+    // starting over picks up the source as it is now
+    let cursors = copy_all(&mut context, &owner, &data, &source, 2, false).await;
+    assert_eq!(cursors, [Some(2), None]);
+    let (data_list, _) = list(&mut context, &data).await;
+    let (source_list, _) = list(&mut context, &source).await;
+    assert_eq!(
+        entries(&data_list)
+            .iter()
+            .map(|entry| entry.key)
+            .collect::<Vec<_>>(),
+        entries(&source_list)
+            .iter()
+            .map(|entry| entry.key)
+            .collect::<Vec<_>>()
+    );
+    // a chunk past the end of a finished copy has nothing to continue
+    let err = send_for_cursor(
+        &mut context,
+        instruction::copy_from(&data, &source, &owner.pubkey(), None, 4, 2),
+        &owner,
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(err.unwrap(), error(ErrorCode::CursorMismatch));
+
+    let err = send_for_cursor(
+        &mut context,
+        instruction::merge_from(&data, &source, &owner.pubkey(), None, 0, 0),
+        &owner,
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(err.unwrap(), error(ErrorCode::InvalidLimit));
+}
+
+#[tokio::test]
+async fn keeps_the_largest_chunks_within_the_default_compute_budget() {
+    let owner = Keypair::new();
+    let mut program_test = program_test();
+    program_test.add_account(owner.pubkey(), system_account(1_000_000_000));
+    let mut context = program_test.start_with_context().await;
+
+    let source = initialize(&mut context, &owner, "source", ListMode::Append).await;
+    let keys = (0..MAX_COPY_ENTRIES)
+        .map(|_| Pubkey::new_unique())
+        .collect::<Vec<_>>();
+    // long enough that a merge chunk of `MAX_COPY_ENTRIES` takes up the
+    // whole comparison budget
+    let data = initialize(&mut context, &owner, "", ListMode::Append).await;
+    let own = (0..MAX_MERGE_COMPARES / MAX_COPY_ENTRIES)
+        .map(|_| Pubkey::new_unique())
+        .collect::<Vec<_>>();
+    for (list, keys) in [(source, &keys), (data, &own)] {
+        for chunk in keys.chunks(25) {
+            send(
+                &mut context,
+                &[instruction::add_many(
+                    &list,
+                    &owner.pubkey(),
+                    None,
+                    chunk.to_vec(),
+                    0,
+                    None,
+                )],
+                &[&owner],
+            )
+            .await
+            .unwrap();
+        }
+    }
+
+    let fork = initialize(&mut context, &owner, "fork", ListMode::Append).await;
+    for (data, merge) in [(fork, false), (data, true)] {
+        let build = if merge {
+            instruction::merge_from
+        } else {
+            instruction::copy_from
+        };
+        let blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
+        let transaction = Transaction::new_signed_with_payer(
+            &[build(&data, &source, &owner.pubkey(), None, 0, u16::MAX)],
+            Some(&context.payer.pubkey()),
+            &[&context.payer, &owner],
+            blockhash,
+        );
+        let result = context
+            .banks_client
+            .process_transaction_with_metadata(transaction)
+            .await
+            .unwrap();
+        result.result.unwrap();
+        let metadata = result.metadata.unwrap();
+        assert_eq!(
+            Option::<u32>::try_from_slice(&metadata.return_data.unwrap().data).unwrap(),
+            None
+        );
+        assert!(
+            metadata.compute_units_consumed < 200_000,
+            "{} CU",
+            metadata.compute_units_consumed
+        );
+    }
+}
@@ -0,0 +1,404 @@
+//! In-process tests of named lists and the directory of them.
+
+use anchor_lang::prelude::{Pubkey, Rent};
+use anchor_lang::{AnchorSerialize, Discriminator};
+use realloc_demo::merkle::MerkleTree;
+use realloc_demo::migrate::DataV1;
+use realloc_demo_client::{
+    decode_data, decode_directory, decode_name, instruction, pda, Data, Directory, DirectoryEntry,
+    Entry, ErrorCode, GrowthPolicy, ListMode, ID,
+};
+use solana_sdk::account::Account;
+use solana_sdk::instruction::InstructionError;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::TransactionError;
+
+mod common;
+
+use common::{account, program_test, send, system_account};
+
+#[tokio::test]
+async fn keeps_several_named_lists_in_a_directory() {
+    let operator = Keypair::new();
+    let mut program_test = program_test();
+    program_test.add_account(operator.pubkey(), system_account(1_000_000_000));
+    let mut context = program_test.start_with_context().await;
+
+    let names = ["allowlist", "blocklist", "partners"];
+    for name in names {
+        send(
+            &mut context,
+            &[instruction::initialize(
+                &operator.pubkey(),
+                name,
+                GrowthPolicy::ExactFit,
+                ListMode::Set,
+            )],
+            &[&operator],
+        )
+        .await
+        .unwrap();
+    }
+
+    let blocked = Pubkey::new_unique();
+    let blocklist = pda::data(&operator.pubkey(), "blocklist").0;
+    send(
+        &mut context,
+        &[instruction::add(
+            &blocklist,
+            &operator.pubkey(),
+            None,
+            blocked,
+            0,
+            None,
+        )],
+        &[&operator],
+    )
+    .await
+    .unwrap();
+
+    for name in names {
+        let data = account(&mut context, &pda::data(&operator.pubkey(), name).0).await;
+        let data = decode_data(&data.data).unwrap();
+        assert_eq!(decode_name(&data.header.name), name);
+        let expected = if name == "blocklist" {
+            vec![blocked]
+        } else {
+            vec![]
+        };
+        assert_eq!(
+            data.list.iter().map(|entry| entry.key).collect::<Vec<_>>(),
+            expected
+        );
+    }
+
+    let directory_address = pda::directory(&operator.pubkey()).0;
+    let directory = account(&mut context, &directory_address).await;
+    assert_eq!(
+        directory.data.len(),
+        Directory::INIT_SIZE + 3 * DirectoryEntry::SIZE
+    );
+    let directory = decode_directory(&directory.data).unwrap();
+    assert_eq!(directory.authority, operator.pubkey());
+    assert_eq!(
+        directory
+            .lists
+            .iter()
+            .map(|entry| (decode_name(&entry.name), entry.data))
+            .collect::<Vec<_>>(),
+        names
+            .iter()
+            .map(|name| (name.to_string(), pda::data(&operator.pubkey(), name).0))
+            .collect::<Vec<_>>()
+    );
+
+    // closing a list takes it out of the directory and refunds its entry
+    let recipient = Pubkey::new_unique();
+    send(
+        &mut context,
+        &[instruction::close(
+            &blocklist,
+            &operator.pubkey(),
+            None,
+            &recipient,
+        )],
+        &[&operator],
+    )
+    .await
+    .unwrap();
+
+    let directory = account(&mut context, &directory_address).await;
+    assert_eq!(
+        directory.data.len(),
+        Directory::INIT_SIZE + 2 * DirectoryEntry::SIZE
+    );
+    let directory = decode_directory(&directory.data).unwrap();
+    assert_eq!(
+        directory
+            .lists
+            .iter()
+            .map(|entry| decode_name(&entry.name))
+            .collect::<Vec<_>>(),
+        ["allowlist", "partners"]
+    );
+    assert!(context
+        .banks_client
+        .get_account(blocklist)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn moves_a_list_to_the_directory_of_its_new_authority() {
+    let creator = Keypair::new();
+    let successor = Keypair::new();
+    let mut program_test = program_test();
+    program_test.add_account(creator.pubkey(), system_account(1_000_000_000));
+    program_test.add_account(successor.pubkey(), system_account(1_000_000_000));
+    let mut context = program_test.start_with_context().await;
+
+    let data = pda::data(&creator.pubkey(), "partners").0;
+    send(
+        &mut context,
+        &[
+            instruction::initialize(
+                &creator.pubkey(),
+                "partners",
+                GrowthPolicy::ExactFit,
+                ListMode::Append,
+            ),
+            instruction::transfer_authority(&data, &creator.pubkey(), successor.pubkey()),
+        ],
+        &[&creator],
+    )
+    .await
+    .unwrap();
+
+    let creator_directory = pda::directory(&creator.pubkey()).0;
+    let creator_lamports = account(&mut context, &creator.pubkey()).await.lamports;
+    send(
+        &mut context,
+        &[instruction::accept_authority(
+            &data,
+            &creator.pubkey(),
+            &successor.pubkey(),
+        )],
+        &[&successor],
+    )
+    .await
+    .unwrap();
+
+    // the previous authority gets the rent of their entry back
+    let directory = account(&mut context, &creator_directory).await;
+    assert_eq!(directory.data.len(), Directory::INIT_SIZE);
+    assert!(decode_directory(&directory.data).unwrap().lists.is_empty());
+    let rent = Rent::default();
+    assert_eq!(
+        account(&mut context, &creator.pubkey()).await.lamports - creator_lamports,
+        rent.minimum_balance(Directory::INIT_SIZE + DirectoryEntry::SIZE)
+            - rent.minimum_balance(Directory::INIT_SIZE)
+    );
+
+    let successor_directory = pda::directory(&successor.pubkey()).0;
+    let directory = account(&mut context, &successor_directory).await;
+    let directory = decode_directory(&directory.data).unwrap();
+    assert_eq!(directory.authority, successor.pubkey());
+    assert_eq!(
+        directory
+            .lists
+            .iter()
+            .map(|entry| (decode_name(&entry.name), entry.data))
+            .collect::<Vec<_>>(),
+        [("partners".to_string(), data)]
+    );
+
+    // closing refunds the entry to the authority who paid for it, and only
+    // the list itself to the recipient
+    let recipient = Pubkey::new_unique();
+    let list_lamports = account(&mut context, &data).await.lamports;
+    let successor_lamports = account(&mut context, &successor.pubkey()).await.lamports;
+    send(
+        &mut context,
+        &[instruction::close(
+            &data,
+            &successor.pubkey(),
+            None,
+            &recipient,
+        )],
+        &[&successor],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        account(&mut context, &recipient).await.lamports,
+        list_lamports
+    );
+    assert_eq!(
+        account(&mut context, &successor.pubkey()).await.lamports - successor_lamports,
+        rent.minimum_balance(Directory::INIT_SIZE + DirectoryEntry::SIZE)
+            - rent.minimum_balance(Directory::INIT_SIZE)
+    );
+    let directory = account(&mut context, &successor_directory).await;
+    assert!(decode_directory(&directory.data).unwrap().lists.is_empty());
+}
+
+#[tokio::test]
+async fn rejects_reused_and_invalid_names() {
+    let operator = Keypair::new();
+    let mut program_test = program_test();
+    program_test.add_account(operator.pubkey(), system_account(1_000_000_000));
+    let mut context = program_test.start_with_context().await;
+
+    let initialize = |name| {
+        instruction::initialize(
+            &operator.pubkey(),
+            name,
+            GrowthPolicy::ExactFit,
+            ListMode::Append,
+        )
+    };
+    send(&mut context, &[initialize("partners")], &[&operator])
+        .await
+        .unwrap();
+
+    // the account already exists
+    let err = send(
+        &mut context,
+        &[
+            initialize("partners"),
+            // differs from the first transaction
+            instruction::contains(
+                &pda::data(&operator.pubkey(), "partners").0,
+                operator.pubkey(),
+            ),
+        ],
+        &[&operator],
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(
+        err.unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(0))
+    ));
+
+    let err = send(&mut context, &[initialize("part\0ners")], &[&operator])
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(ErrorCode::InvalidName.into())
+        )
+    );
+}
+
+/// A v1 account holding `keys`, sized the way the v1 program grew it.
+fn v1_account(keys: &[Pubkey]) -> Account {
+    let mut bytes = Data::DISCRIMINATOR.to_vec();
+    DataV1 {
+        list: keys.to_vec(),
+    }
+    .serialize(&mut bytes)
+    .unwrap();
+    let step = DataV1::GROWTH_ENTRIES as usize * 32;
+    bytes.resize(DataV1::INIT_SIZE + keys.len().div_ceil(10) * step, 0);
+
+    Account {
+        lamports: Rent::default().minimum_balance(bytes.len()),
+        data: bytes,
+        owner: ID,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+#[tokio::test]
+async fn migrates_an_unnamed_list_into_the_directory() {
+    let creator = Keypair::new();
+    let address = pda::data(&creator.pubkey(), "").0;
+    let keys = (0..3).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+
+    let mut program_test = program_test();
+    program_test.add_account(creator.pubkey(), system_account(1_000_000_000));
+    program_test.add_account(address, v1_account(&keys));
+    let mut context = program_test.start_with_context().await;
+
+    send(
+        &mut context,
+        &[instruction::migrate(&creator.pubkey())],
+        &[&creator],
+    )
+    .await
+    .unwrap();
+
+    let data = account(&mut context, &address).await;
+    assert_eq!(data.data.len(), Data::INIT_SIZE + 3 * Entry::SIZE);
+    let data = decode_data(&data.data).unwrap();
+    assert_eq!(data.header.version, Data::VERSION);
+    assert_eq!(data.header.authority, creator.pubkey());
+    assert_eq!(data.header.creator, creator.pubkey());
+    assert_eq!(data.header.name, [0; 32]);
+    assert_eq!(data.header.growth, GrowthPolicy::Fixed { entries: 10 });
+    assert_eq!(
+        (data.header.merkle, data.header.merkle_len),
+        (MerkleTree::build(&keys), 3)
+    );
+    assert_eq!(
+        data.list,
+        keys.iter()
+            .enumerate()
+            .map(|(leaf, key)| Entry {
+                key: *key,
+                added_by: creator.pubkey(),
+                timestamp: 0,
+                slot: 0,
+                tag: 0,
+                expires_at: 0,
+                leaf: leaf as u32,
+            })
+            .collect::<Vec<_>>()
+    );
+
+    let directory = account(&mut context, &pda::directory(&creator.pubkey()).0).await;
+    let directory = decode_directory(&directory.data).unwrap();
+    assert_eq!(
+        directory.lists,
+        [DirectoryEntry {
+            name: [0; 32],
+            data: address,
+        }]
+    );
+
+    let err = send(
+        &mut context,
+        &[instruction::migrate(&creator.pubkey())],
+        &[&creator],
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(
+        err.unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(ErrorCode::AlreadyMigrated.into())
+        )
+    );
+}
+
+#[tokio::test]
+async fn migrates_a_long_list_over_several_calls() {
+    let creator = Keypair::new();
+    let address = pda::data(&creator.pubkey(), "").0;
+    let keys = (0..300).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+
+    let mut program_test = program_test();
+    program_test.add_account(creator.pubkey(), system_account(1_000_000_000));
+    program_test.add_account(address, v1_account(&keys));
+    let mut context = program_test.start_with_context().await;
+
+    let needed_len = Data::INIT_SIZE + keys.len() * Entry::SIZE;
+    let mut calls = 0;
+    while account(&mut context, &address).await.data.len() < needed_len {
+        send(
+            &mut context,
+            &[instruction::migrate(&creator.pubkey())],
+            &[&creator],
+        )
+        .await
+        .unwrap();
+        context.get_new_latest_blockhash().await.unwrap();
+        calls += 1;
+    }
+    // one call to grow, one to grow the rest of the way and rewrite
+    assert_eq!(calls, 2);
+
+    let data = account(&mut context, &address).await;
+    assert_eq!(data.data.len(), needed_len);
+    let data = decode_data(&data.data).unwrap();
+    assert_eq!(
+        data.list.iter().map(|entry| entry.key).collect::<Vec<_>>(),
+        keys
+    );
+}
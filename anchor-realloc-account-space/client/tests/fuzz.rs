@@ -0,0 +1,277 @@
+//! Runs random sequences of list operations against `realloc_demo` and
+//! checks the `Data` account against a model of the list after every step.
+
+use anchor_lang::prelude::{Pubkey, Rent};
+use proptest::collection::vec;
+use proptest::prelude::*;
+use realloc_demo::merkle::{self, MerkleTree};
+use realloc_demo_client::{
+    decode_data, entries, instruction, merkle_proof, pda, Data, Entry, GrowthPolicy, ListMode,
+};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::compute_budget::ComputeBudgetInstruction;
+use solana_sdk::signature::{Keypair, Signer};
+
+mod common;
+
+use common::{account, program_test, send, system_account};
+
+/// Size of the key pool, small enough that operations keep hitting keys
+/// already in the list.
+const KEYS: u8 = 12;
+
+/// Lamports the authority starts with. Every top-up comes from and every
+/// refund goes back to it, so it, its directory and the list always hold
+/// this much between them.
+const LAMPORTS: u64 = 10_000_000_000;
+
+#[derive(Clone, Debug)]
+enum Op {
+    Add(u8),
+    AddMany(Vec<u8>),
+    Remove(u8),
+    Reserve(u8),
+    ShrinkToFit,
+}
+
+fn key(index: u8) -> Pubkey {
+    Pubkey::new_from_array([index + 1; 32])
+}
+
+/// The entries the list should hold, as their key and leaf in list order,
+/// and the leaves of its merkle tree.
+struct Model {
+    mode: ListMode,
+    entries: Vec<(Pubkey, usize)>,
+    /// The key of every leaf in use, `None` once it was emptied.
+    leaves: Vec<Option<Pubkey>>,
+}
+
+impl Model {
+    fn keys(&self) -> Vec<Pubkey> {
+        self.entries.iter().map(|(key, _)| *key).collect()
+    }
+
+    fn leaf_hashes(&self) -> Vec<[u8; 32]> {
+        self.leaves
+            .iter()
+            .map(|leaf| leaf.map_or([0; 32], |key| merkle::leaf(&key)))
+            .collect()
+    }
+
+    /// Position in the list of the entry `remove` takes out for `key`.
+    fn position(&self, key: &Pubkey) -> Option<usize> {
+        self.entries.iter().position(|(k, _)| k == key)
+    }
+
+    /// Proof of `key` for `remove`, empty if it isn't in the list.
+    fn proof(&self, key: &Pubkey) -> Vec<[u8; 32]> {
+        match self.position(key) {
+            Some(position) => merkle_proof(&self.leaf_hashes(), self.entries[position].1),
+            None => Vec::new(),
+        }
+    }
+
+    /// Adds `key` the way the program does, or returns false if the program
+    /// should reject it.
+    fn insert(&mut self, key: Pubkey) -> bool {
+        if self.entries.is_empty() {
+            self.leaves.clear();
+        }
+        let entry = (key, self.leaves.len());
+        match self.mode {
+            ListMode::Append => self.entries.push(entry),
+            ListMode::Set => match self.entries.binary_search_by_key(&key, |(k, _)| *k) {
+                Ok(_) => return false,
+                Err(index) => self.entries.insert(index, entry),
+            },
+            ListMode::Ring { capacity } => {
+                // the evicted key keeps its leaf
+                if self.entries.len() == capacity as usize {
+                    self.entries.remove(0);
+                }
+                self.entries.push(entry);
+            }
+        }
+        self.leaves.push(Some(key));
+        true
+    }
+
+    /// Applies `op`, or returns false and leaves the model as it is if the
+    /// program should reject it.
+    fn apply(&mut self, op: &Op) -> bool {
+        match op {
+            Op::Add(index) => self.insert(key(*index)),
+            Op::AddMany(indices) => {
+                let before = (self.entries.clone(), self.leaves.clone());
+                let added = indices.iter().all(|index| self.insert(key(*index)));
+                if !added {
+                    (self.entries, self.leaves) = before;
+                }
+                added
+            }
+            Op::Remove(index) => match self.position(&key(*index)) {
+                Some(position) => {
+                    let (_, leaf) = self.entries.remove(position);
+                    self.leaves[leaf] = None;
+                    true
+                }
+                None => false,
+            },
+            Op::Reserve(_) | Op::ShrinkToFit => true,
+        }
+    }
+}
+
+fn growth() -> impl Strategy<Value = GrowthPolicy> {
+    prop_oneof![
+        (1u16..=4).prop_map(|entries| GrowthPolicy::Fixed { entries }),
+        Just(GrowthPolicy::Doubling),
+        Just(GrowthPolicy::ExactFit),
+    ]
+}
+
+fn mode() -> impl Strategy<Value = ListMode> {
+    prop_oneof![
+        Just(ListMode::Append),
+        Just(ListMode::Set),
+        (1u32..=6).prop_map(|capacity| ListMode::Ring { capacity }),
+    ]
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        4 => (0..KEYS).prop_map(Op::Add),
+        1 => vec(0..KEYS, 1..=5).prop_map(Op::AddMany),
+        3 => (0..KEYS).prop_map(Op::Remove),
+        1 => (0u8..=20).prop_map(Op::Reserve),
+        1 => Just(Op::ShrinkToFit),
+    ]
+}
+
+async fn check_invariants(
+    context: &mut ProgramTestContext,
+    rent: &Rent,
+    authority: &Pubkey,
+    model: &Model,
+) {
+    let data = pda::data(authority, "").0;
+    let data_account = account(context, &data).await;
+    let list = decode_data(&data_account.data).unwrap();
+    let size = data_account.data.len();
+
+    assert!(size >= Data::INIT_SIZE + list.list.len() * Entry::SIZE);
+    if let ListMode::Ring { capacity } = model.mode {
+        assert!(size <= Data::INIT_SIZE + capacity as usize * Entry::SIZE);
+    }
+    assert_eq!(list.header.count as usize, list.list.len());
+    assert_eq!(
+        list.header.capacity as usize,
+        (size - Data::INIT_SIZE) / Entry::SIZE
+    );
+    assert!(rent.is_exempt(data_account.lamports, size));
+
+    let keys = entries(&list)
+        .iter()
+        .map(|entry| entry.key)
+        .collect::<Vec<_>>();
+    assert_eq!(keys, model.keys());
+    // every entry holds its own leaf, and can be proven with it
+    assert_eq!(list.header.merkle_len as usize, model.leaves.len());
+    let leaves = model.leaf_hashes();
+    for (entry, (key, leaf)) in entries(&list).iter().zip(&model.entries) {
+        assert_eq!(entry.leaf as usize, *leaf);
+        assert!(merkle::verify(
+            &list.header.merkle.root,
+            key,
+            *leaf,
+            &merkle_proof(&leaves, *leaf)
+        ));
+    }
+    if model.leaves.iter().all(Option::is_none) {
+        assert_eq!(list.header.merkle.root, MerkleTree::empty().root);
+    }
+
+    let authority_lamports = account(context, authority).await.lamports;
+    let directory_lamports = account(context, &pda::directory(authority).0)
+        .await
+        .lamports;
+    assert_eq!(
+        authority_lamports + directory_lamports + data_account.lamports,
+        LAMPORTS
+    );
+}
+
+async fn run(growth: GrowthPolicy, mode: ListMode, ops: Vec<Op>) {
+    let authority = Keypair::new();
+    let mut program_test = program_test();
+    program_test.add_account(authority.pubkey(), system_account(LAMPORTS));
+    let mut context = program_test.start_with_context().await;
+    let rent = context.banks_client.get_rent().await.unwrap();
+
+    send(
+        &mut context,
+        &[instruction::initialize(
+            &authority.pubkey(),
+            "",
+            growth,
+            mode,
+        )],
+        &[&authority],
+    )
+    .await
+    .unwrap();
+    let data = pda::data(&authority.pubkey(), "").0;
+    let mut model = Model {
+        mode,
+        entries: Vec::new(),
+        leaves: Vec::new(),
+    };
+    check_invariants(&mut context, &rent, &authority.pubkey(), &model).await;
+
+    for (step, op) in ops.iter().enumerate() {
+        let signer = authority.pubkey();
+        let instruction = match op {
+            Op::Add(index) => instruction::add(&data, &signer, None, key(*index), 0, None),
+            Op::AddMany(indices) => instruction::add_many(
+                &data,
+                &signer,
+                None,
+                indices.iter().map(|index| key(*index)).collect(),
+                0,
+                None,
+            ),
+            Op::Remove(index) => {
+                instruction::remove(&data, &signer, None, key(*index), model.proof(&key(*index)))
+            }
+            Op::Reserve(additional) => instruction::reserve(&data, &signer, *additional as u32),
+            Op::ShrinkToFit => instruction::shrink_to_fit(&data, &signer, None),
+        };
+        // keeps repeated operations from being deduplicated as the same
+        // transaction
+        let nonce = ComputeBudgetInstruction::set_compute_unit_price(step as u64);
+
+        let expected = model.apply(op);
+        let result = send(&mut context, &[instruction, nonce], &[&authority]).await;
+        assert_eq!(result.is_ok(), expected, "step {step}, {op:?}: {result:?}");
+
+        check_invariants(&mut context, &rent, &authority.pubkey(), &model).await;
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn list_invariants_hold_after_every_step(
+        growth in growth(),
+        mode in mode(),
+        ops in vec(op(), 1..30),
+    ) {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(run(growth, mode, ops));
+    }
+}
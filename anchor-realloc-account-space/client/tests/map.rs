@@ -0,0 +1,150 @@
+//! In-process tests of maps too large to deserialize on the program heap.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::AnchorDeserialize;
+use realloc_demo_client::{decode_map, instruction, pda, ErrorCode, GrowthPolicy, Map, MapEntry};
+use solana_program_test::{BanksClientError, ProgramTestContext};
+use solana_sdk::instruction::InstructionError;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::{Transaction, TransactionError};
+
+mod common;
+
+use common::{account, program_test, send, system_account};
+
+/// Sends `get` for `key` and decodes the value it returns.
+async fn get(
+    context: &mut ProgramTestContext,
+    map: &Pubkey,
+    key: Pubkey,
+) -> Result<Option<u64>, BanksClientError> {
+    let blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
+    let transaction = Transaction::new_signed_with_payer(
+        &[instruction::get(map, key)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer],
+        blockhash,
+    );
+    let result = context
+        .banks_client
+        .process_transaction_with_metadata(transaction)
+        .await?;
+    result.result?;
+
+    let return_data = result.metadata.unwrap().return_data.unwrap();
+    Ok(Option::<u64>::try_from_slice(&return_data.data).unwrap())
+}
+
+#[tokio::test]
+async fn keeps_maps_far_larger_than_the_heap() {
+    let authority = Keypair::new();
+    let mut program_test = program_test();
+    program_test.add_account(authority.pubkey(), system_account(10_000_000_000));
+    let mut context = program_test.start_with_context().await;
+
+    send(
+        &mut context,
+        &[instruction::initialize_map(
+            &authority.pubkey(),
+            GrowthPolicy::Doubling,
+        )],
+        &[&authority],
+    )
+    .await
+    .unwrap();
+
+    // 1200 entries take 48000 bytes, beyond the 32KiB heap even before
+    // `Account<Map>` copies them into a `Vec`
+    let keys = (0..1200).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+    for (chunk_index, chunk) in keys.chunks(10).enumerate() {
+        let instructions = chunk
+            .iter()
+            .enumerate()
+            .map(|(i, key)| {
+                instruction::upsert(&authority.pubkey(), *key, (chunk_index * 10 + i) as u64)
+            })
+            .collect::<Vec<_>>();
+        send(&mut context, &instructions, &[&authority])
+            .await
+            .unwrap();
+    }
+
+    let map = pda::map(&authority.pubkey()).0;
+    let stored = decode_map(&account(&mut context, &map).await.data).unwrap();
+    let mut expected = keys
+        .iter()
+        .enumerate()
+        .map(|(value, key)| MapEntry {
+            key: *key,
+            value: value as u64,
+        })
+        .collect::<Vec<_>>();
+    expected.sort_by_key(|entry| entry.key);
+    assert_eq!(stored.entries, expected);
+
+    assert_eq!(get(&mut context, &map, keys[700]).await.unwrap(), Some(700));
+    assert_eq!(
+        get(&mut context, &map, Pubkey::new_unique()).await.unwrap(),
+        None
+    );
+
+    // overwrite, increment and delete in the middle of the map
+    send(
+        &mut context,
+        &[
+            instruction::upsert(&authority.pubkey(), keys[3], 1),
+            instruction::increment(&authority.pubkey(), keys[700], 5),
+            instruction::delete(&authority.pubkey(), keys[500]),
+            instruction::delete(&authority.pubkey(), expected[0].key),
+        ],
+        &[&authority],
+    )
+    .await
+    .unwrap();
+    assert_eq!(get(&mut context, &map, keys[3]).await.unwrap(), Some(1));
+    assert_eq!(get(&mut context, &map, keys[700]).await.unwrap(), Some(705));
+    assert_eq!(get(&mut context, &map, keys[500]).await.unwrap(), None);
+
+    let stored = decode_map(&account(&mut context, &map).await.data).unwrap();
+    assert_eq!(stored.entries.len(), keys.len() - 2);
+    assert!(stored
+        .entries
+        .windows(2)
+        .all(|pair| pair[0].key < pair[1].key));
+    let data_len = account(&mut context, &map).await.data.len();
+    assert!(data_len >= Map::INIT_SIZE + stored.entries.len() * MapEntry::SIZE);
+
+    let err = send(
+        &mut context,
+        &[instruction::delete(&authority.pubkey(), keys[500])],
+        &[&authority],
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(
+        err.unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(ErrorCode::KeyNotFound.into())
+        )
+    );
+
+    let err = send(
+        &mut context,
+        &[instruction::increment(
+            &authority.pubkey(),
+            keys[700],
+            u64::MAX,
+        )],
+        &[&authority],
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(
+        err.unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(ErrorCode::ValueOverflow.into())
+        )
+    );
+}
@@ -0,0 +1,328 @@
+//! In-process tests of the merkle tree over list keys.
+
+use anchor_lang::prelude::{Clock, Pubkey};
+use realloc_demo::merkle::{self, MerkleTree};
+use realloc_demo_client::{
+    decode_data, entries, instruction, merkle_leaves, merkle_proof, pda, Data, ErrorCode,
+    GrowthPolicy, ListMode,
+};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::instruction::InstructionError;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::TransactionError;
+
+mod common;
+
+use common::{account, program_test, send, system_account};
+
+async fn list(context: &mut ProgramTestContext, data: &Pubkey) -> Data {
+    decode_data(&account(context, data).await.data).unwrap()
+}
+
+/// Proof of `key` against the current tree of `data`.
+fn proof(data: &Data, key: &Pubkey) -> Vec<[u8; 32]> {
+    let entry = entries(data).into_iter().find(|e| e.key == *key).unwrap();
+    merkle_proof(&merkle_leaves(data), entry.leaf as usize)
+}
+
+fn custom(error: ErrorCode) -> TransactionError {
+    TransactionError::InstructionError(0, InstructionError::Custom(error.into()))
+}
+
+#[tokio::test]
+async fn appends_every_key_and_empties_its_leaf_by_proof() {
+    let authority = Keypair::new();
+    let mut program_test = program_test();
+    program_test.add_account(authority.pubkey(), system_account(1_000_000_000));
+    let mut context = program_test.start_with_context().await;
+
+    send(
+        &mut context,
+        &[instruction::initialize(
+            &authority.pubkey(),
+            "",
+            GrowthPolicy::Doubling,
+            ListMode::Set,
+        )],
+        &[&authority],
+    )
+    .await
+    .unwrap();
+    let data = pda::data(&authority.pubkey(), "").0;
+
+    // a set stores keys sorted, the tree keeps them in the order they came
+    let mut keys = (0..6).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+    keys.sort();
+    keys.reverse();
+    send(
+        &mut context,
+        &[instruction::add_many(
+            &data,
+            &authority.pubkey(),
+            None,
+            keys.clone(),
+            0,
+            None,
+        )],
+        &[&authority],
+    )
+    .await
+    .unwrap();
+    let stored = list(&mut context, &data).await;
+    assert_eq!(stored.header.merkle_len, 6);
+    assert_eq!(stored.header.merkle, MerkleTree::build(&keys));
+    assert_eq!(entries(&stored)[0].key, keys[5]);
+    assert_eq!(entries(&stored)[0].leaf, 5);
+    for key in &keys {
+        send(
+            &mut context,
+            &[instruction::verify_membership(
+                &data,
+                *key,
+                proof(&stored, key),
+            )],
+            &[],
+        )
+        .await
+        .unwrap();
+    }
+
+    // removing empties the leaf in place, and keeps the others provable
+    let stale = proof(&stored, &keys[3]);
+    send(
+        &mut context,
+        &[instruction::remove(
+            &data,
+            &authority.pubkey(),
+            None,
+            keys[2],
+            proof(&stored, &keys[2]),
+        )],
+        &[&authority],
+    )
+    .await
+    .unwrap();
+    let stored = list(&mut context, &data).await;
+    assert_eq!(stored.header.merkle_len, 6);
+    let mut leaves = keys.iter().map(merkle::leaf).collect::<Vec<_>>();
+    leaves[2] = [0; 32];
+    assert_eq!(merkle_leaves(&stored), leaves);
+
+    let err = send(
+        &mut context,
+        &[instruction::verify_membership(
+            &data,
+            keys[3],
+            stale.clone(),
+        )],
+        &[],
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(err.unwrap(), custom(ErrorCode::InvalidProof));
+    let err = send(
+        &mut context,
+        &[instruction::remove(
+            &data,
+            &authority.pubkey(),
+            None,
+            keys[3],
+            stale,
+        )],
+        &[&authority],
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(err.unwrap(), custom(ErrorCode::InvalidProof));
+
+    // a removed key can't be proven with its old leaf, and is added again
+    // as a new one
+    let err = send(
+        &mut context,
+        &[instruction::verify_membership(
+            &data,
+            keys[2],
+            merkle_proof(&leaves, 2),
+        )],
+        &[],
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(err.unwrap(), custom(ErrorCode::EntryNotFound));
+    send(
+        &mut context,
+        &[instruction::add(
+            &data,
+            &authority.pubkey(),
+            None,
+            keys[2],
+            0,
+            None,
+        )],
+        &[&authority],
+    )
+    .await
+    .unwrap();
+    let stored = list(&mut context, &data).await;
+    leaves.push(merkle::leaf(&keys[2]));
+    assert_eq!(merkle_leaves(&stored), leaves);
+    for key in &keys {
+        send(
+            &mut context,
+            &[instruction::verify_membership(
+                &data,
+                *key,
+                proof(&stored, key),
+            )],
+            &[],
+        )
+        .await
+        .unwrap();
+    }
+
+    // emptying the list starts the tree over
+    for key in &keys {
+        let stored = list(&mut context, &data).await;
+        send(
+            &mut context,
+            &[instruction::remove(
+                &data,
+                &authority.pubkey(),
+                None,
+                *key,
+                proof(&stored, key),
+            )],
+            &[&authority],
+        )
+        .await
+        .unwrap();
+    }
+    let stored = list(&mut context, &data).await;
+    assert_eq!(stored.header.merkle.root, MerkleTree::empty().root);
+    send(
+        &mut context,
+        &[instruction::add(
+            &data,
+            &authority.pubkey(),
+            None,
+            keys[0],
+            0,
+            None,
+        )],
+        &[&authority],
+    )
+    .await
+    .unwrap();
+    let stored = list(&mut context, &data).await;
+    assert_eq!(stored.header.merkle_len, 1);
+    assert_eq!(stored.header.merkle, MerkleTree::build(&keys[..1]));
+}
+
+#[tokio::test]
+async fn prunes_with_a_proof_per_entry_and_stops_proving_expired_ones() {
+    let authority = Keypair::new();
+    let caller = Keypair::new();
+    let mut program_test = program_test();
+    program_test.add_account(authority.pubkey(), system_account(1_000_000_000));
+    program_test.add_account(caller.pubkey(), system_account(1_000_000_000));
+    let mut context = program_test.start_with_context().await;
+
+    send(
+        &mut context,
+        &[instruction::initialize(
+            &authority.pubkey(),
+            "",
+            GrowthPolicy::ExactFit,
+            ListMode::Append,
+        )],
+        &[&authority],
+    )
+    .await
+    .unwrap();
+    let data = pda::data(&authority.pubkey(), "").0;
+
+    let mut clock = context.banks_client.get_sysvar::<Clock>().await.unwrap();
+    let keys = (0..5).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+    for (i, key) in keys.iter().enumerate() {
+        // every other entry expires
+        let expires_at = (i % 2 == 0).then_some(clock.unix_timestamp + 100);
+        send(
+            &mut context,
+            &[instruction::add(
+                &data,
+                &authority.pubkey(),
+                None,
+                *key,
+                0,
+                expires_at,
+            )],
+            &[&authority],
+        )
+        .await
+        .unwrap();
+    }
+    let stored = list(&mut context, &data).await;
+    let expired_proof = proof(&stored, &keys[0]);
+
+    clock.unix_timestamp += 200;
+    context.set_sysvar(&clock);
+
+    // expired entries are out of the list as far as `is_member` goes, and
+    // so for `verify_membership` too
+    let err = send(
+        &mut context,
+        &[instruction::verify_membership(
+            &data,
+            keys[0],
+            expired_proof,
+        )],
+        &[],
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(err.unwrap(), custom(ErrorCode::EntryNotFound));
+
+    // each proof is taken after the leaves before it are emptied
+    let mut leaves = merkle_leaves(&stored);
+    let mut proofs = Vec::new();
+    for leaf in [0, 2] {
+        proofs.push(merkle_proof(&leaves, leaf));
+        leaves[leaf] = [0; 32];
+    }
+    send(
+        &mut context,
+        &[instruction::prune_expired(
+            &data,
+            &authority.pubkey(),
+            None,
+            &caller.pubkey(),
+            proofs,
+        )],
+        &[&caller],
+    )
+    .await
+    .unwrap();
+    let stored = list(&mut context, &data).await;
+    assert_eq!(
+        entries(&stored).iter().map(|e| e.key).collect::<Vec<_>>(),
+        [keys[1], keys[3], keys[4]]
+    );
+    assert_eq!(merkle_leaves(&stored), leaves);
+
+    // the last expired entry is left for lack of a proof, and pruning
+    // without any removes nothing
+    let err = send(
+        &mut context,
+        &[instruction::prune_expired(
+            &data,
+            &authority.pubkey(),
+            None,
+            &caller.pubkey(),
+            Vec::new(),
+        )],
+        &[&caller],
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(err.unwrap(), custom(ErrorCode::NothingToPrune));
+}
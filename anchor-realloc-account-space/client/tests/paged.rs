@@ -0,0 +1,129 @@
+//! In-process tests of paged lists.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::AnchorDeserialize;
+use realloc_demo_client::{
+    decode_page, decode_paged_list, instruction, pda, DataPage, ErrorCode, PagedSlice,
+};
+use solana_program_test::{BanksClientError, ProgramTestContext};
+use solana_sdk::instruction::InstructionError;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::{Transaction, TransactionError};
+
+mod common;
+
+use common::{account, program_test, send, system_account};
+
+/// Sends `list_paged` for `list` and decodes the slice it returns.
+async fn list_paged(
+    context: &mut ProgramTestContext,
+    list: &Pubkey,
+    pages: &[Pubkey],
+    offset: u64,
+    limit: u16,
+) -> Result<PagedSlice, BanksClientError> {
+    let blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
+    let transaction = Transaction::new_signed_with_payer(
+        &[instruction::list_paged(list, pages, offset, limit)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer],
+        blockhash,
+    );
+    let result = context
+        .banks_client
+        .process_transaction_with_metadata(transaction)
+        .await?;
+    result.result?;
+
+    let return_data = result.metadata.unwrap().return_data.unwrap();
+    Ok(PagedSlice::try_from_slice(&return_data.data).unwrap())
+}
+
+#[tokio::test]
+async fn fills_full_pages_in_place_and_lists_across_them() {
+    let authority = Keypair::new();
+    let mut program_test = program_test();
+    program_test.add_account(authority.pubkey(), system_account(10_000_000_000));
+    let mut context = program_test.start_with_context().await;
+
+    let capacity = DataPage::MAX_CAPACITY;
+    send(
+        &mut context,
+        &[instruction::initialize_paged(
+            &authority.pubkey(),
+            capacity as u16,
+        )],
+        &[&authority],
+    )
+    .await
+    .unwrap();
+
+    // one full page and a few entries on the next
+    let keys = (0..capacity + 12)
+        .map(|_| Pubkey::new_unique())
+        .collect::<Vec<_>>();
+    for (chunk_index, chunk) in keys.chunks(10).enumerate() {
+        let instructions = chunk
+            .iter()
+            .enumerate()
+            .map(|(i, key)| {
+                let page_index = ((chunk_index * 10 + i) / capacity) as u32;
+                instruction::add_paged(&authority.pubkey(), page_index, *key)
+            })
+            .collect::<Vec<_>>();
+        send(&mut context, &instructions, &[&authority])
+            .await
+            .unwrap();
+    }
+
+    let list = pda::paged_list(&authority.pubkey()).0;
+    let header = decode_paged_list(&account(&mut context, &list).await.data).unwrap();
+    assert_eq!(header.len, keys.len() as u64);
+    assert_eq!(header.page_count, 2);
+
+    let pages = [pda::page(&list, 0).0, pda::page(&list, 1).0];
+    let first = decode_page(&account(&mut context, &pages[0]).await.data).unwrap();
+    assert_eq!(first.entries, keys[..capacity]);
+    let second = decode_page(&account(&mut context, &pages[1]).await.data).unwrap();
+    assert_eq!(second.index, 1);
+    assert_eq!(second.entries, keys[capacity..]);
+
+    // a window spanning both pages
+    let offset = capacity as u64 - 5;
+    let slice = list_paged(&mut context, &list, &pages, offset, 10)
+        .await
+        .unwrap();
+    assert_eq!(slice.total, keys.len() as u64);
+    assert_eq!(slice.entries, keys[offset as usize..][..10]);
+
+    // capped to what fits in return data, starting on the second page
+    let slice = list_paged(&mut context, &list, &pages[1..], capacity as u64, u16::MAX)
+        .await
+        .unwrap();
+    assert_eq!(slice.entries, keys[capacity..]);
+
+    let slice = list_paged(&mut context, &list, &pages, 0, u16::MAX)
+        .await
+        .unwrap();
+    assert_eq!(slice.entries, keys[..PagedSlice::MAX_ENTRIES]);
+
+    // past the end
+    let slice = list_paged(&mut context, &list, &[], keys.len() as u64, 10)
+        .await
+        .unwrap();
+    assert!(slice.entries.is_empty());
+
+    // pages out of order, or missing
+    let invalid_page = TransactionError::InstructionError(
+        0,
+        InstructionError::Custom(ErrorCode::InvalidPage.into()),
+    );
+    let err = list_paged(&mut context, &list, &[pages[1], pages[0]], offset, 10)
+        .await
+        .unwrap_err();
+    assert_eq!(err.unwrap(), invalid_page);
+    let err = list_paged(&mut context, &list, &pages[..1], offset, 10)
+        .await
+        .unwrap_err();
+    assert_eq!(err.unwrap(), invalid_page);
+}
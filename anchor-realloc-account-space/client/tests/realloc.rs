@@ -0,0 +1,376 @@
+//! In-process tests of the realloc paths of `realloc_demo`, driven through
+//! the client crate.
+
+use anchor_lang::prelude::{Pubkey, Rent};
+use anchor_lang::AccountSerialize;
+use realloc_demo::directory::MAX_NAME_LEN;
+use realloc_demo::merkle::MerkleTree;
+use realloc_demo_client::{
+    decode_data, instruction, merkle_leaves, merkle_proof, pda, predict_add_top_up, CopyProgress,
+    Data, DataHeader, Entry, ErrorCode, GrowthPolicy, ListMode, ID,
+};
+use solana_program_test::ProgramTest;
+use solana_sdk::account::Account;
+use solana_sdk::instruction::InstructionError;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::TransactionError;
+
+mod common;
+
+use common::{account, program_test, send, system_account};
+
+/// Adds a `Data` list of `authority` to `program_test` holding `len`
+/// entries and `free` spare bytes, funded to be rent-exempt.
+fn add_list(
+    program_test: &mut ProgramTest,
+    authority: &Pubkey,
+    growth: GrowthPolicy,
+    len: usize,
+    free: usize,
+) -> Pubkey {
+    let (address, bump) = pda::data(authority, "");
+    let list = (0..len)
+        .map(|leaf| Entry {
+            key: Pubkey::new_unique(),
+            added_by: *authority,
+            timestamp: 0,
+            slot: 0,
+            tag: 0,
+            expires_at: 0,
+            leaf: leaf as u32,
+        })
+        .collect::<Vec<_>>();
+    let keys = list.iter().map(|entry| entry.key).collect::<Vec<_>>();
+
+    let mut data = Data {
+        header: DataHeader {
+            version: Data::VERSION,
+            bump,
+            mode: ListMode::Append,
+            authority: *authority,
+            creator: *authority,
+            name: [0; MAX_NAME_LEN],
+            pending_authority: Pubkey::default(),
+            sealed: false,
+            capacity: 0,
+            count: 0,
+            head: 0,
+            tail: 0,
+            prune_reward_bps: 0,
+            merkle: MerkleTree::build(&keys),
+            merkle_len: len as u32,
+            growth,
+            sponsor: Pubkey::default(),
+            sponsored: 0,
+            copy: CopyProgress::default(),
+        },
+        list,
+    };
+    let size = Data::INIT_SIZE + len * Entry::SIZE + free;
+    data.update_header(size);
+
+    let mut bytes = vec![0; size];
+    data.try_serialize(&mut &mut bytes[..]).unwrap();
+    program_test.add_account(
+        address,
+        Account {
+            lamports: Rent::default().minimum_balance(size),
+            data: bytes,
+            owner: ID,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+
+    address
+}
+
+/// Sizes of the list of `authority` after adding one entry to a list with
+/// one entry and `free` spare bytes.
+async fn size_after_add(growth: GrowthPolicy, free: usize) -> (usize, usize) {
+    let authority = Keypair::new();
+    let mut program_test = program_test();
+    program_test.add_account(authority.pubkey(), system_account(1_000_000_000));
+    let data = add_list(&mut program_test, &authority.pubkey(), growth, 1, free);
+    let mut context = program_test.start_with_context().await;
+
+    let before = account(&mut context, &data).await.data.len();
+    send(
+        &mut context,
+        &[instruction::add(
+            &data,
+            &authority.pubkey(),
+            None,
+            Pubkey::new_unique(),
+            0,
+            None,
+        )],
+        &[&authority],
+    )
+    .await
+    .unwrap();
+    let after = account(&mut context, &data).await;
+
+    let list = decode_data(&after.data).unwrap();
+    assert_eq!(list.list.len(), 2);
+    assert_eq!(
+        list.header.capacity as usize,
+        (after.data.len() - Data::INIT_SIZE) / Entry::SIZE
+    );
+
+    (before, after.data.len())
+}
+
+#[tokio::test]
+async fn grows_only_once_less_than_an_entry_is_left() {
+    let growth = GrowthPolicy::Fixed { entries: 4 };
+
+    // exactly one entry of room: written in place
+    let (before, after) = size_after_add(growth, Entry::SIZE).await;
+    assert_eq!(after, before);
+
+    // one byte short: grows by one step
+    let (before, after) = size_after_add(growth, Entry::SIZE - 1).await;
+    assert_eq!(after, before + 4 * Entry::SIZE);
+
+    // exact fit grows by just the missing byte
+    let (before, after) = size_after_add(GrowthPolicy::ExactFit, Entry::SIZE - 1).await;
+    assert_eq!(after, before + 1);
+}
+
+#[tokio::test]
+async fn tops_up_rent_by_the_predicted_amount() {
+    let authority = Keypair::new();
+    let sponsor = Keypair::new();
+    let mut program_test = program_test();
+    program_test.add_account(authority.pubkey(), system_account(1_000_000_000));
+    program_test.add_account(sponsor.pubkey(), system_account(1_000_000_000));
+    let mut context = program_test.start_with_context().await;
+    let rent = context.banks_client.get_rent().await.unwrap();
+
+    send(
+        &mut context,
+        &[instruction::initialize(
+            &authority.pubkey(),
+            "",
+            GrowthPolicy::Fixed { entries: 3 },
+            ListMode::Append,
+        )],
+        &[&authority],
+    )
+    .await
+    .unwrap();
+    let data = pda::data(&authority.pubkey(), "").0;
+
+    for expected_size in [
+        Data::INIT_SIZE + 3 * Entry::SIZE,
+        Data::INIT_SIZE + 3 * Entry::SIZE,
+        Data::INIT_SIZE + 3 * Entry::SIZE,
+        Data::INIT_SIZE + 6 * Entry::SIZE,
+    ] {
+        let before = account(&mut context, &data).await;
+        let predicted = predict_add_top_up(&before.data, before.lamports, &rent).unwrap();
+        let sponsor_before = account(&mut context, &sponsor.pubkey()).await.lamports;
+
+        send(
+            &mut context,
+            &[instruction::add(
+                &data,
+                &authority.pubkey(),
+                Some(&sponsor.pubkey()),
+                Pubkey::new_unique(),
+                0,
+                None,
+            )],
+            &[&authority, &sponsor],
+        )
+        .await
+        .unwrap();
+
+        let after = account(&mut context, &data).await;
+        let sponsor_after = account(&mut context, &sponsor.pubkey()).await.lamports;
+        assert_eq!(after.data.len(), expected_size);
+        assert_eq!(after.lamports, rent.minimum_balance(expected_size));
+        assert_eq!(sponsor_before - sponsor_after, predicted);
+        assert_eq!(after.lamports - before.lamports, predicted);
+    }
+}
+
+#[tokio::test]
+async fn fails_when_the_signer_cannot_afford_the_top_up() {
+    let authority = Keypair::new();
+    let mut program_test = program_test();
+    let growth = GrowthPolicy::Fixed { entries: 100 };
+    let data = add_list(&mut program_test, &authority.pubkey(), growth, 0, 0);
+    // enough to stay rent-exempt, not to pay for 100 more entries
+    program_test.add_account(authority.pubkey(), system_account(1_000_000));
+    let mut context = program_test.start_with_context().await;
+
+    let before = account(&mut context, &data).await;
+    let err = send(
+        &mut context,
+        &[instruction::add(
+            &data,
+            &authority.pubkey(),
+            None,
+            Pubkey::new_unique(),
+            0,
+            None,
+        )],
+        &[&authority],
+    )
+    .await
+    .unwrap_err();
+
+    // the system program's `ResultWithNegativeLamports`
+    assert!(matches!(
+        err.unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(1))
+    ));
+
+    let after = account(&mut context, &data).await;
+    assert_eq!(after.data, before.data);
+    assert_eq!(after.lamports, before.lamports);
+    assert_eq!(
+        account(&mut context, &authority.pubkey()).await.lamports,
+        1_000_000
+    );
+}
+
+#[tokio::test]
+async fn repays_the_sponsor_before_refunding_the_authority() {
+    let authority = Keypair::new();
+    let sponsor = Keypair::new();
+    let other_sponsor = Keypair::new();
+    let mut program_test = program_test();
+    for wallet in [&authority, &sponsor, &other_sponsor] {
+        program_test.add_account(wallet.pubkey(), system_account(1_000_000_000));
+    }
+    let mut context = program_test.start_with_context().await;
+
+    send(
+        &mut context,
+        &[instruction::initialize(
+            &authority.pubkey(),
+            "",
+            GrowthPolicy::ExactFit,
+            ListMode::Append,
+        )],
+        &[&authority],
+    )
+    .await
+    .unwrap();
+    let data = pda::data(&authority.pubkey(), "").0;
+    let initial = account(&mut context, &data).await.lamports;
+
+    let keys = [Pubkey::new_unique(), Pubkey::new_unique()];
+    for key in keys {
+        send(
+            &mut context,
+            &[instruction::add(
+                &data,
+                &authority.pubkey(),
+                Some(&sponsor.pubkey()),
+                key,
+                0,
+                None,
+            )],
+            &[&authority, &sponsor],
+        )
+        .await
+        .unwrap();
+    }
+    let sponsored = account(&mut context, &data).await.lamports - initial;
+    let list = decode_data(&account(&mut context, &data).await.data).unwrap();
+    assert_eq!(list.header.sponsor, sponsor.pubkey());
+    assert_eq!(list.header.sponsored, sponsored);
+    let proof = merkle_proof(&merkle_leaves(&list), 0);
+
+    let sponsor_mismatch = TransactionError::InstructionError(
+        0,
+        InstructionError::Custom(ErrorCode::SponsorMismatch.into()),
+    );
+
+    // a second sponsor has to wait until the first is repaid
+    let err = send(
+        &mut context,
+        &[instruction::add(
+            &data,
+            &authority.pubkey(),
+            Some(&other_sponsor.pubkey()),
+            Pubkey::new_unique(),
+            0,
+            None,
+        )],
+        &[&authority, &other_sponsor],
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(err.unwrap(), sponsor_mismatch);
+
+    // the authority can't take the sponsored rent by shrinking the list
+    for sponsor in [None, Some(&other_sponsor.pubkey())] {
+        let err = send(
+            &mut context,
+            &[instruction::remove(
+                &data,
+                &authority.pubkey(),
+                sponsor,
+                keys[0],
+                proof.clone(),
+            )],
+            &[&authority],
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.unwrap(), sponsor_mismatch);
+    }
+
+    let authority_before = account(&mut context, &authority.pubkey()).await.lamports;
+    let sponsor_before = account(&mut context, &sponsor.pubkey()).await.lamports;
+    send(
+        &mut context,
+        &[instruction::remove(
+            &data,
+            &authority.pubkey(),
+            Some(&sponsor.pubkey()),
+            keys[0],
+            proof,
+        )],
+        &[&authority],
+    )
+    .await
+    .unwrap();
+    let repaid = account(&mut context, &sponsor.pubkey()).await.lamports - sponsor_before;
+    assert!(repaid > 0);
+    assert_eq!(
+        account(&mut context, &authority.pubkey()).await.lamports,
+        authority_before
+    );
+    let list = decode_data(&account(&mut context, &data).await.data).unwrap();
+    assert_eq!(list.header.sponsored, sponsored - repaid);
+
+    // closing repays the rest, and only what is left goes to the recipient
+    let recipient = Pubkey::new_unique();
+    let lamports = account(&mut context, &data).await.lamports;
+    send(
+        &mut context,
+        &[instruction::close(
+            &data,
+            &authority.pubkey(),
+            Some(&sponsor.pubkey()),
+            &recipient,
+        )],
+        &[&authority],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        account(&mut context, &sponsor.pubkey()).await.lamports - sponsor_before,
+        sponsored
+    );
+    assert_eq!(
+        account(&mut context, &recipient).await.lamports,
+        lamports - (sponsored - repaid)
+    );
+}
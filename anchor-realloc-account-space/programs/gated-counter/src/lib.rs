@@ -0,0 +1,92 @@
+//! Example consumer of `realloc_demo` lists: a counter that only wallets on
+//! an allowlist can increment, checked with a CPI to `is_member`.
+
+use anchor_lang::prelude::*;
+use realloc_demo::cpi::accounts::IsMember;
+use realloc_demo::program::ReallocDemo;
+
+declare_id!("Aqj2H9ev17vMqNa5MebxdDRJhR9vTYFatfStnFdJNSrT");
+
+#[program]
+mod gated_counter {
+    use super::*;
+
+    pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
+        ctx.accounts.counter.allowlist = ctx.accounts.allowlist.key();
+        ctx.accounts.counter.bump = ctx.bumps.counter;
+        ctx.accounts.counter.count = 0;
+
+        msg!("Counter account: {}", ctx.accounts.counter.key());
+
+        Ok(())
+    }
+
+    pub fn increment(ctx: Context<Increment>) -> Result<()> {
+        let allowed = realloc_demo::cpi::is_member(
+            CpiContext::new(
+                ctx.accounts.realloc_demo_program.to_account_info(),
+                IsMember {
+                    data: ctx.accounts.allowlist.to_account_info(),
+                },
+            ),
+            ctx.accounts.user.key(),
+        )?
+        .get();
+        require!(allowed, ErrorCode::NotAllowed);
+
+        ctx.accounts.counter.count += 1;
+        msg!("Count: {}", ctx.accounts.counter.count);
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    #[account(
+        init,
+        seeds = [b"counter".as_ref(), allowlist.key().as_ref()], bump,
+        payer = signer,
+        space = Counter::SIZE
+    )]
+    pub counter: Account<'info, Counter>,
+
+    /// CHECK: the `realloc_demo` list gating `increment`, checked by
+    /// `is_member` on every call
+    pub allowlist: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Increment<'info> {
+    #[account(
+        mut,
+        seeds = [b"counter".as_ref(), allowlist.key().as_ref()], bump = counter.bump,
+        has_one = allowlist
+    )]
+    pub counter: Account<'info, Counter>,
+
+    /// CHECK: checked by `is_member`
+    pub allowlist: UncheckedAccount<'info>,
+    pub user: Signer<'info>,
+    pub realloc_demo_program: Program<'info, ReallocDemo>,
+}
+
+#[account]
+pub struct Counter {
+    /// The `realloc_demo` `Data` account listing who may increment.
+    pub allowlist: Pubkey,
+    pub bump: u8,
+    pub count: u64,
+}
+impl Counter {
+    pub const SIZE: usize = 8 + 32 + 1 + 8;
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Signer is not on the allowlist")]
+    NotAllowed,
+}
@@ -0,0 +1,233 @@
+//! Copying entries from one `Data` list into another, to snapshot or fork a
+//! list.
+//!
+//! A list can hold more entries than one instruction can move, so both
+//! `copy_from` and `merge_from` work in chunks: each call reads at most
+//! `MAX_COPY_ENTRIES` entries of the source from a cursor on and returns
+//! the cursor to resume from, or `None` once the whole source has been
+//! read. Entries are copied as they are, keeping who added them and when,
+//! and each one is announced with `EntryAdded` as `add_many` does.
+//!
+//! The cursor is an index into the source, so a source that changes
+//! between chunks would be copied torn. The chunk at cursor 0 records the
+//! source, its entry count and its merkle root in the destination's
+//! `CopyProgress`, and every later chunk has to continue from the cursor
+//! recorded there, from a source that still matches. A source that has
+//! changed has to be copied again from the start.
+//!
+//! Merging checks every entry against the whole destination, which outside
+//! of a set means reading it end to end, so a merge chunk into an append
+//! list or a ring is also cut down to keep that within
+//! `MAX_MERGE_COMPARES` key comparisons.
+
+use anchor_lang::prelude::*;
+
+use crate::events::{emitter, EntriesCopied, EntryAdded, EntryRemoved, ListCleared};
+use crate::raw::{self, RawData};
+use crate::{grow, Data, Entry, ErrorCode, ListMode};
+
+/// Most entries a chunk copies. Announcing an entry with `emit_cpi!` costs
+/// a self-CPI signed by the event authority, about 3k CU, so this keeps a
+/// chunk within the default budget of 200k CU.
+pub const MAX_COPY_ENTRIES: usize = 40;
+
+/// Most destination keys a `merge_from` chunk into an append list or a ring
+/// compares against. A comparison costs about 20 CU, so these take up to
+/// 40k CU on top of the entries the chunk copies.
+pub const MAX_MERGE_COMPARES: usize = 2_000;
+
+/// A copy or merge in progress into a list, which every chunk after the
+/// first has to continue.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct CopyProgress {
+    /// List being copied from, or the default key if there is none.
+    pub source: Pubkey,
+    /// Cursor the next chunk reads `source` from.
+    pub cursor: u32,
+    /// Entries `source` held when the copy started.
+    pub source_count: u32,
+    /// Merkle root of `source` when the copy started. Adding or removing
+    /// any key moves it.
+    pub source_root: [u8; 32],
+}
+impl CopyProgress {
+    pub const SIZE: usize = 32 + 4 + 4 + 32;
+}
+
+/// Replaces the entries of the list with those of `source`. Starting from
+/// cursor 0 clears the list first. Into a set, `source` can't hold a key
+/// twice.
+pub fn copy_from(ctx: Context<CopyEntries>, cursor: u32, limit: u16) -> Result<Option<u32>> {
+    msg!("Copying from {} at {}", ctx.accounts.source.key(), cursor);
+    copy(ctx, cursor, limit, false)
+}
+
+/// Adds the entries of `source` whose key isn't in the list yet.
+pub fn merge_from(ctx: Context<CopyEntries>, cursor: u32, limit: u16) -> Result<Option<u32>> {
+    msg!("Merging from {} at {}", ctx.accounts.source.key(), cursor);
+    copy(ctx, cursor, limit, true)
+}
+
+/// Adds up to `limit` entries of `source` from `cursor` on, skipping keys
+/// the list already has if `merge` is set, in which case fewer are read the
+/// longer the list is. Returns the cursor of the next chunk.
+fn copy(ctx: Context<CopyEntries>, cursor: u32, limit: u16, merge: bool) -> Result<Option<u32>> {
+    let data_account_info = ctx.accounts.data.to_account_info();
+    let source_account_info = ctx.accounts.source.to_account_info();
+    require_keys_neq!(
+        data_account_info.key(),
+        source_account_info.key(),
+        ErrorCode::SameList
+    );
+
+    require!(limit > 0, ErrorCode::InvalidLimit);
+    let (source_header, _) = raw::read_header(&source_account_info)?;
+
+    let mut data = RawData::load(&data_account_info, ctx.accounts.signer.key)?;
+    if cursor == 0 {
+        data.header.copy = CopyProgress {
+            source: source_account_info.key(),
+            cursor,
+            source_count: source_header.count,
+            source_root: source_header.merkle.root,
+        };
+    }
+    let progress = data.header.copy;
+    require!(
+        progress.source == source_account_info.key() && progress.cursor == cursor,
+        ErrorCode::CursorMismatch
+    );
+    require!(
+        progress.source_count == source_header.count
+            && progress.source_root == source_header.merkle.root,
+        ErrorCode::SourceChanged
+    );
+
+    let mut limit = (limit as usize).min(MAX_COPY_ENTRIES);
+    if merge && data.header.mode != ListMode::Set {
+        limit = (MAX_MERGE_COMPARES / data.len().max(1)).clamp(1, limit);
+    }
+    let (chunk, source_len) = raw::read_entries(&source_account_info, cursor as usize, limit)?;
+
+    if !merge && cursor == 0 {
+        data.clear();
+
+        let cleared = ListCleared {
+            data: data_account_info.key(),
+        };
+        emit!(cleared);
+        emit_cpi!(cleared);
+    }
+
+    let mut entries: Vec<Entry> = Vec::with_capacity(chunk.len());
+    let mut skipped = Vec::new();
+    for entry in &chunk {
+        if merge && (data.contains(&entry.key)? || entries.iter().any(|e| e.key == entry.key)) {
+            skipped.push(entry.key);
+            continue;
+        }
+        entries.push(*entry);
+    }
+
+    let curr_data_size = data_account_info.data_len();
+    let required_size = data
+        .header
+        .mode
+        .clamp_size(Data::INIT_SIZE + (data.len() + entries.len()) * Entry::SIZE);
+
+    msg!("current data size: {}", curr_data_size);
+    msg!("required data size: {}", required_size);
+
+    if required_size > curr_data_size {
+        let needed_len = data
+            .header
+            .growth
+            .grown_size(Data::LAYOUT, curr_data_size, required_size);
+        let needed_len = data.header.mode.clamp_size(needed_len);
+        let payer = ctx.accounts.rent_payer();
+        let paid = grow(
+            &data_account_info,
+            &payer,
+            &ctx.accounts.system_program.to_account_info(),
+            &ctx.accounts.rent,
+            needed_len,
+            &emitter!(ctx),
+        )?;
+        data.header.record_payment(payer.key, paid)?;
+    }
+
+    for entry in &entries {
+        let (index, evicted) = data.insert(entry)?;
+
+        if let Some(evicted) = evicted {
+            let removed = EntryRemoved {
+                data: data_account_info.key(),
+                entry: evicted,
+                index: 0,
+            };
+            emit!(removed);
+            emit_cpi!(removed);
+        }
+
+        let added = EntryAdded {
+            data: data_account_info.key(),
+            entry: entry.key,
+            index: index as u32,
+        };
+        emit!(added);
+        emit_cpi!(added);
+    }
+
+    let next = cursor as usize + chunk.len();
+    data.header.copy = if next < source_len {
+        CopyProgress {
+            cursor: next as u32,
+            ..progress
+        }
+    } else {
+        CopyProgress::default()
+    };
+    data.save()?;
+
+    let copied = EntriesCopied {
+        data: data_account_info.key(),
+        source: source_account_info.key(),
+        cursor,
+        count: entries.len() as u32,
+        skipped,
+    };
+    emit!(copied);
+    emit_cpi!(copied);
+
+    Ok((next < source_len).then_some(next as u32))
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CopyEntries<'info> {
+    /// CHECK: the `Data` list copied into, accessed through `RawData`.
+    /// Checked in `RawData::load`.
+    #[account(mut)]
+    pub data: UncheckedAccount<'info>,
+    /// CHECK: the `Data` list copied from, read in place. Checked in
+    /// `raw::read_header`.
+    pub source: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    /// Funds rent top-ups instead of `signer` when present, and is recorded
+    /// as the list's sponsor as with `add`.
+    #[account(mut)]
+    pub payer: Option<Signer<'info>>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> CopyEntries<'info> {
+    pub fn rent_payer(&self) -> AccountInfo<'info> {
+        match &self.payer {
+            Some(payer) => payer.to_account_info(),
+            None => self.signer.to_account_info(),
+        }
+    }
+}
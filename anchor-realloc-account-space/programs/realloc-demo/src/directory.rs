@@ -0,0 +1,150 @@
+//! Named lists and the per-wallet directory of them.
+//!
+//! A wallet can create any number of `Data` lists, each at
+//! `[b"data", creator, name]`. The empty name gives `[b"data", creator]`,
+//! the address every list had before lists were named. A `Directory` PDA
+//! seeded by `[b"directory", authority]` records each list a wallet is the
+//! authority of: lists it made or migrated, and lists handed over to it by
+//! `accept_authority`, which takes them out of the previous authority's
+//! directory. `close` takes lists out again. A directory is created along
+//! with its first list and grows by one entry per list, paid for by its
+//! authority.
+
+use anchor_lang::prelude::*;
+
+use crate::events::Emitter;
+use crate::{create_pda, grow, shrink, ErrorCode};
+
+/// Longest name a list can have, the most a single seed can hold.
+pub const MAX_NAME_LEN: usize = 32;
+
+/// `name` as stored in `Data`, padded with zeros.
+pub fn encode_name(name: &str) -> Result<[u8; MAX_NAME_LEN]> {
+    let bytes = name.as_bytes();
+    require!(
+        bytes.len() <= MAX_NAME_LEN && !bytes.contains(&0),
+        ErrorCode::InvalidName
+    );
+
+    let mut encoded = [0; MAX_NAME_LEN];
+    encoded[..bytes.len()].copy_from_slice(bytes);
+    Ok(encoded)
+}
+
+/// The seed of a stored name, i.e. the name without its padding.
+pub fn name_seed(name: &[u8; MAX_NAME_LEN]) -> &[u8] {
+    let len = name.iter().position(|b| *b == 0).unwrap_or(MAX_NAME_LEN);
+    &name[..len]
+}
+
+/// Records the list `data` named `name` in the directory of `authority` at
+/// `directory`, creating the directory first if this is their first list.
+/// `authority` pays for the directory growing by one entry.
+pub fn register<'info>(
+    directory: &AccountInfo<'info>,
+    bump: u8,
+    authority: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    name: [u8; MAX_NAME_LEN],
+    data: Pubkey,
+    events: &Emitter<'info>,
+) -> Result<()> {
+    if directory.data_is_empty() {
+        create(directory, bump, authority, system_program)?;
+    }
+
+    require_keys_eq!(
+        *directory.owner,
+        crate::ID,
+        anchor_lang::error::ErrorCode::AccountOwnedByWrongProgram
+    );
+    let mut account = Directory::try_deserialize(&mut &directory.try_borrow_data()?[..])?;
+    grow(
+        directory,
+        authority,
+        system_program,
+        &Rent::get()?,
+        Directory::INIT_SIZE + (account.lists.len() + 1) * DirectoryEntry::SIZE,
+        events,
+    )?;
+    account.lists.push(DirectoryEntry { name, data });
+    account.try_serialize(&mut &mut directory.try_borrow_mut_data()?[..])?;
+
+    msg!(
+        "Directory {} lists {} lists",
+        directory.key(),
+        account.lists.len()
+    );
+
+    Ok(())
+}
+
+/// Creates the empty directory of `authority`.
+fn create<'info>(
+    directory: &AccountInfo<'info>,
+    bump: u8,
+    authority: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+) -> Result<()> {
+    let signer_seeds: &[&[u8]] = &[b"directory".as_ref(), authority.key.as_ref(), &[bump]];
+
+    create_pda(
+        directory,
+        authority,
+        system_program,
+        Directory::INIT_SIZE,
+        signer_seeds,
+    )?;
+
+    Directory {
+        authority: *authority.key,
+        bump,
+        lists: Vec::new(),
+    }
+    .try_serialize(&mut &mut directory.try_borrow_mut_data()?[..])
+}
+
+/// Takes the list `data` out of `directory`, shrinking it and refunding the
+/// rent of the entry to `authority`, the directory's owner who paid for it.
+pub fn unregister<'info>(
+    directory: &mut Account<'info, Directory>,
+    data: &Pubkey,
+    authority: &AccountInfo<'info>,
+    events: &Emitter<'info>,
+) -> Result<()> {
+    let Some(index) = directory.lists.iter().position(|entry| entry.data == *data) else {
+        return Ok(());
+    };
+    directory.lists.remove(index);
+
+    shrink(
+        &directory.to_account_info(),
+        authority,
+        &Rent::get()?,
+        Directory::INIT_SIZE + directory.lists.len() * DirectoryEntry::SIZE,
+        events,
+    )?;
+
+    Ok(())
+}
+
+#[account]
+pub struct Directory {
+    pub authority: Pubkey,
+    pub bump: u8,
+    /// In the order the lists were registered.
+    pub lists: Vec<DirectoryEntry>,
+}
+impl Directory {
+    pub const INIT_SIZE: usize = 8 + 32 + 1 + 4;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DirectoryEntry {
+    /// Padded with zeros, see `name_seed`.
+    pub name: [u8; MAX_NAME_LEN],
+    pub data: Pubkey,
+}
+impl DirectoryEntry {
+    pub const SIZE: usize = MAX_NAME_LEN + 32;
+}
@@ -0,0 +1,146 @@
+//! Events emitted on every change to a list, both to the program log and as
+//! self-CPIs, so indexers can rebuild list state without parsing `msg!` lines.
+
+use anchor_lang::event::EVENT_IX_TAG_LE;
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::log::sol_log_data;
+use anchor_lang::solana_program::program::invoke_signed;
+use anchor_lang::Event;
+
+/// Emits events the way `emit!` and `emit_cpi!` do, for helpers such as
+/// `grow` that get accounts rather than the instruction's `Context`.
+pub struct Emitter<'info> {
+    event_authority: AccountInfo<'info>,
+    bump: u8,
+}
+
+impl<'info> Emitter<'info> {
+    pub fn new(event_authority: AccountInfo<'info>, bump: u8) -> Self {
+        Self {
+            event_authority,
+            bump,
+        }
+    }
+
+    pub fn emit(&self, event: &impl Event) -> Result<()> {
+        let data = event.data();
+        sol_log_data(&[&data]);
+
+        let ix = Instruction::new_with_bytes(
+            crate::ID,
+            &EVENT_IX_TAG_LE.into_iter().chain(data).collect::<Vec<_>>(),
+            vec![AccountMeta::new_readonly(*self.event_authority.key, true)],
+        );
+        invoke_signed(
+            &ix,
+            std::slice::from_ref(&self.event_authority),
+            &[&[b"__event_authority", &[self.bump]]],
+        )?;
+        Ok(())
+    }
+}
+
+/// The `Emitter` of the `#[event_cpi]` instruction `ctx` belongs to.
+macro_rules! emitter {
+    ($ctx:expr) => {
+        $crate::events::Emitter::new(
+            $ctx.accounts.event_authority.to_account_info(),
+            $ctx.bumps.event_authority,
+        )
+    };
+}
+pub(crate) use emitter;
+
+#[event]
+pub struct EntryAdded {
+    pub data: Pubkey,
+    pub entry: Pubkey,
+    pub index: u32,
+}
+
+#[event]
+pub struct EntryRemoved {
+    pub data: Pubkey,
+    pub entry: Pubkey,
+    pub index: u32,
+}
+
+#[event]
+pub struct AccountResized {
+    pub data: Pubkey,
+    pub old_size: u32,
+    pub new_size: u32,
+}
+
+#[event]
+pub struct RentToppedUp {
+    pub data: Pubkey,
+    pub payer: Pubkey,
+    pub lamports: u64,
+}
+
+#[event]
+pub struct EntriesPruned {
+    pub data: Pubkey,
+    pub caller: Pubkey,
+    pub count: u32,
+    pub reward: u64,
+}
+
+#[event]
+pub struct SponsorRepaid {
+    pub data: Pubkey,
+    pub sponsor: Pubkey,
+    pub lamports: u64,
+}
+
+#[event]
+pub struct ValueSet {
+    pub map: Pubkey,
+    pub key: Pubkey,
+    pub value: u64,
+}
+
+#[event]
+pub struct KeyDeleted {
+    pub map: Pubkey,
+    pub key: Pubkey,
+}
+
+#[event]
+pub struct AuthorityTransferred {
+    pub data: Pubkey,
+    pub from: Pubkey,
+    pub to: Pubkey,
+}
+
+#[event]
+pub struct Sealed {
+    pub data: Pubkey,
+}
+
+#[event]
+pub struct ListCleared {
+    pub data: Pubkey,
+}
+
+/// `data` was closed and all its `lamports` sent to `recipient`.
+#[event]
+pub struct ListClosed {
+    pub data: Pubkey,
+    pub recipient: Pubkey,
+    pub lamports: u64,
+}
+
+/// `count` entries of `source`, read from its `cursor`th entry on, were
+/// added to `data`, each announced by its own `EntryAdded`. A merge skips
+/// the keys `data` already had, listed in `skipped`.
+#[event]
+pub struct EntriesCopied {
+    pub data: Pubkey,
+    pub source: Pubkey,
+    pub cursor: u32,
+    pub count: u32,
+    pub skipped: Vec<Pubkey>,
+}
@@ -1,4 +1,24 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::entrypoint::MAX_PERMITTED_DATA_INCREASE;
+use anchor_lang::solana_program::program::MAX_RETURN_DATA;
+
+pub mod copy;
+pub mod directory;
+pub mod events;
+pub mod map;
+pub mod merkle;
+pub mod migrate;
+pub mod paged;
+pub mod raw;
+
+use copy::*;
+use directory::*;
+use events::{emitter, *};
+use map::*;
+use merkle::MerkleTree;
+use migrate::*;
+use paged::*;
+use raw::RawData;
 
 // This is your program's public key and it will update
 // automatically when you build the project.
@@ -7,90 +27,1050 @@ declare_id!("FXFWZVRVm76Dw59UtcEGVWqno5C4U379sdo1PF423Z8A");
 #[program]
 mod realloc_demo {
     use super::*;
-    pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
+    /// Creates the list `name` of the signer and records it in the
+    /// signer's directory, which is created along with their first list.
+    pub fn initialize(
+        ctx: Context<Initialize>,
+        name: String,
+        growth: GrowthPolicy,
+        mode: ListMode,
+    ) -> Result<()> {
+        growth.validate()?;
+        mode.validate()?;
+        let name = encode_name(&name)?;
+
+        let signer = ctx.accounts.signer.key();
+        ctx.accounts.data.header = DataHeader {
+            version: Data::VERSION,
+            bump: ctx.bumps.data,
+            mode,
+            authority: signer,
+            creator: signer,
+            name,
+            pending_authority: Pubkey::default(),
+            sealed: false,
+            capacity: 0,
+            count: 0,
+            head: 0,
+            tail: 0,
+            prune_reward_bps: 0,
+            merkle: MerkleTree::empty(),
+            merkle_len: 0,
+            growth,
+            sponsor: Pubkey::default(),
+            sponsored: 0,
+            copy: CopyProgress::default(),
+        };
         ctx.accounts.data.list = Vec::new();
+        ctx.accounts.data.update_header(Data::INIT_SIZE);
 
         msg!(
             "Data account: {}",
             ctx.accounts.data.to_account_info().key()
         );
 
-        return Ok(());
+        register(
+            &ctx.accounts.directory,
+            ctx.bumps.directory,
+            &ctx.accounts.signer,
+            &ctx.accounts.system_program,
+            name,
+            ctx.accounts.data.key(),
+            &emitter!(ctx),
+        )?;
+
+        Ok(())
     }
 
-    pub fn add(ctx: Context<Add>, entry: Pubkey) -> Result<()> {
+    /// Adds `entry`, which can be pruned once `expires_at` (a unix
+    /// timestamp) has passed.
+    pub fn add(ctx: Context<Add>, entry: Pubkey, tag: u8, expires_at: Option<i64>) -> Result<()> {
         msg!("Adding new entry to vector: {}", entry);
         let data_account_info = ctx.accounts.data.to_account_info();
+        let mut data = RawData::load(&data_account_info, ctx.accounts.signer.key)?;
 
         let curr_data_size = data_account_info.data_len();
-        let space_left = curr_data_size - ctx.accounts.data.list.len() * 32 - Data::INIT_SIZE;
+        let space_left = curr_data_size
+            .checked_sub(Data::INIT_SIZE + data.len() * Entry::SIZE)
+            .ok_or(ErrorCode::CorruptLayout)?;
 
         msg!("current data size: {}", curr_data_size);
-        msg!("current length of list: {}", ctx.accounts.data.list.len());
+        msg!("current length of list: {}", data.len());
         msg!("space left: {}", space_left);
 
-        if space_left < 32 {
-            let needed_len = curr_data_size + 10 * 32;
-            AccountInfo::realloc(&data_account_info, needed_len, false)?;
-
-            // if more lamports are needed, transfer them to the account
-            let rent_exempt_lamports =
-                ctx.accounts.rent.minimum_balance(needed_len).max(1);
-            let top_up_lamports =
-                rent_exempt_lamports.saturating_sub(ctx.accounts.data.to_account_info().lamports());
-
-            msg!("top up lamports: {}", top_up_lamports);
-
-            if top_up_lamports > 0 {
-                anchor_lang::system_program::transfer(
-                    anchor_lang::context::CpiContext::new(
-                        ctx.accounts.system_program.to_account_info(),
-                        anchor_lang::system_program::Transfer {
-                            from: ctx.accounts.signer.to_account_info(),
-                            to: ctx.accounts.data.to_account_info(),
-                        },
-                    ),
-                    top_up_lamports
-                )?;
+        if let Some(needed_len) = data.header.grown_size_for_add(curr_data_size) {
+            let payer = ctx.accounts.rent_payer();
+            let paid = grow(
+                &data_account_info,
+                &payer,
+                &ctx.accounts.system_program.to_account_info(),
+                &ctx.accounts.rent,
+                needed_len,
+                &emitter!(ctx),
+            )?;
+            data.header.record_payment(payer.key, paid)?;
+        }
+
+        let curr_data_size = data_account_info.data_len();
+        msg!("current data size after realloc: {}", curr_data_size);
+
+        let (index, evicted) = data.insert(&Entry::new(
+            entry,
+            ctx.accounts.signer.key(),
+            tag,
+            expires_at,
+        )?)?;
+        data.save()?;
+
+        if let Some(evicted) = evicted {
+            let removed = EntryRemoved {
+                data: data_account_info.key(),
+                entry: evicted,
+                index: 0,
+            };
+            emit!(removed);
+            emit_cpi!(removed);
+        }
+
+        let added = EntryAdded {
+            data: data_account_info.key(),
+            entry,
+            index: index as u32,
+        };
+        emit!(added);
+        emit_cpi!(added);
+
+        Ok(())
+    }
+
+    /// Adds every key in `entries`, all with the same `tag` and expiry.
+    pub fn add_many(
+        ctx: Context<Add>,
+        entries: Vec<Pubkey>,
+        tag: u8,
+        expires_at: Option<i64>,
+    ) -> Result<()> {
+        msg!("Adding {} new entries to vector", entries.len());
+        let data_account_info = ctx.accounts.data.to_account_info();
+        let mut data = RawData::load(&data_account_info, ctx.accounts.signer.key)?;
+
+        let curr_data_size = data_account_info.data_len();
+        let required_size = data
+            .header
+            .mode
+            .clamp_size(Data::INIT_SIZE + (data.len() + entries.len()) * Entry::SIZE);
+
+        msg!("current data size: {}", curr_data_size);
+        msg!("required data size: {}", required_size);
+
+        if required_size > curr_data_size {
+            require!(
+                required_size - curr_data_size <= MAX_PERMITTED_DATA_INCREASE,
+                ErrorCode::ReallocLimitExceeded
+            );
+
+            let needed_len =
+                data.header
+                    .growth
+                    .grown_size(Data::LAYOUT, curr_data_size, required_size);
+            let needed_len = data.header.mode.clamp_size(needed_len);
+            let payer = ctx.accounts.rent_payer();
+            let paid = grow(
+                &data_account_info,
+                &payer,
+                &ctx.accounts.system_program.to_account_info(),
+                &ctx.accounts.rent,
+                needed_len,
+                &emitter!(ctx),
+            )?;
+            data.header.record_payment(payer.key, paid)?;
+        }
+
+        let curr_data_size = data_account_info.data_len();
+        msg!("current data size after realloc: {}", curr_data_size);
+
+        for entry in entries {
+            let (index, evicted) = data.insert(&Entry::new(
+                entry,
+                ctx.accounts.signer.key(),
+                tag,
+                expires_at,
+            )?)?;
+
+            if let Some(evicted) = evicted {
+                let removed = EntryRemoved {
+                    data: data_account_info.key(),
+                    entry: evicted,
+                    index: 0,
+                };
+                emit!(removed);
+                emit_cpi!(removed);
             }
+
+            let added = EntryAdded {
+                data: data_account_info.key(),
+                entry,
+                index: index as u32,
+            };
+            emit!(added);
+            emit_cpi!(added);
+        }
+        data.save()?;
+
+        Ok(())
+    }
+
+    /// Removes `entry`, emptying its leaf in the merkle tree. `proof` is
+    /// its proof against the current root, see `verify_membership`.
+    pub fn remove(ctx: Context<Remove>, entry: Pubkey, proof: Vec<[u8; 32]>) -> Result<()> {
+        msg!("Removing entry from vector: {}", entry);
+
+        let data_account_info = ctx.accounts.data.to_account_info();
+        let mut data = RawData::load(&data_account_info, ctx.accounts.signer.key)?;
+        let index = data.remove(&entry, &proof)?;
+
+        let removed = EntryRemoved {
+            data: data_account_info.key(),
+            entry,
+            index: index as u32,
+        };
+        emit!(removed);
+        emit_cpi!(removed);
+
+        let curr_data_size = data_account_info.data_len();
+        let required_size = Data::INIT_SIZE + data.len() * Entry::SIZE;
+        let space_left = curr_data_size
+            .checked_sub(required_size)
+            .ok_or(ErrorCode::CorruptLayout)?;
+
+        msg!("current data size: {}", curr_data_size);
+        msg!("current length of list: {}", data.len());
+        msg!("space left: {}", space_left);
+
+        if let Some(needed_len) =
+            data.header
+                .growth
+                .shrunk_size(Data::LAYOUT, curr_data_size, required_size)?
+        {
+            repay_sponsor(
+                &mut data.header,
+                &data_account_info,
+                ctx.accounts
+                    .sponsor
+                    .as_ref()
+                    .map(ToAccountInfo::to_account_info),
+                ctx.accounts.rent.minimum_balance(needed_len).max(1),
+                &emitter!(ctx),
+            )?;
+            shrink(
+                &data_account_info,
+                &ctx.accounts.signer.to_account_info(),
+                &ctx.accounts.rent,
+                needed_len,
+                &emitter!(ctx),
+            )?;
         }
 
         let curr_data_size = data_account_info.data_len();
         msg!("current data size after realloc: {}", curr_data_size);
 
-        ctx.accounts.data.reload()?;
-        ctx.accounts.data.list.push(entry);
+        data.save()?;
+
+        Ok(())
+    }
+
+    /// Grows the account so that `additional` more entries fit without
+    /// another realloc, paid for by the signer.
+    pub fn reserve(ctx: Context<Resize>, additional: u32) -> Result<()> {
+        msg!("Reserving space for {} more entries", additional);
+        let data_account_info = ctx.accounts.data.to_account_info();
+        let mut data = RawData::load(&data_account_info, ctx.accounts.signer.key)?;
+
+        let curr_data_size = data_account_info.data_len();
+        let required_size = data
+            .header
+            .mode
+            .clamp_size(Data::INIT_SIZE + (data.len() + additional as usize) * Entry::SIZE);
+
+        msg!("current data size: {}", curr_data_size);
+        msg!("required data size: {}", required_size);
+
+        if required_size > curr_data_size {
+            require!(
+                required_size - curr_data_size <= MAX_PERMITTED_DATA_INCREASE,
+                ErrorCode::ReallocLimitExceeded
+            );
+
+            grow(
+                &data_account_info,
+                &ctx.accounts.signer.to_account_info(),
+                &ctx.accounts.system_program.to_account_info(),
+                &ctx.accounts.rent,
+                required_size,
+                &emitter!(ctx),
+            )?;
+
+            data.save()?;
+        }
 
-        return Ok(());
+        Ok(())
+    }
+
+    /// Releases every unused slot and refunds the rent they held to the
+    /// signer, after repaying the list's sponsor.
+    pub fn shrink_to_fit(ctx: Context<Resize>) -> Result<()> {
+        let data_account_info = ctx.accounts.data.to_account_info();
+        let mut data = RawData::load(&data_account_info, ctx.accounts.signer.key)?;
+
+        let curr_data_size = data_account_info.data_len();
+        let needed_len = Data::INIT_SIZE + data.len() * Entry::SIZE;
+
+        msg!("current data size: {}", curr_data_size);
+        msg!("needed data size: {}", needed_len);
+
+        if needed_len < curr_data_size {
+            repay_sponsor(
+                &mut data.header,
+                &data_account_info,
+                ctx.accounts
+                    .sponsor
+                    .as_ref()
+                    .map(ToAccountInfo::to_account_info),
+                ctx.accounts.rent.minimum_balance(needed_len).max(1),
+                &emitter!(ctx),
+            )?;
+            shrink(
+                &data_account_info,
+                &ctx.accounts.signer.to_account_info(),
+                &ctx.accounts.rent,
+                needed_len,
+                &emitter!(ctx),
+            )?;
+
+            data.save()?;
+        }
+
+        Ok(())
+    }
+
+    /// Closes the list, repaying its sponsor and sending the rest of its
+    /// lamports to `recipient`, and takes it out of the signer's directory.
+    pub fn close(ctx: Context<Close>) -> Result<()> {
+        // closed by hand: `Account<Data>` would copy the whole list onto
+        // the heap just to throw it away
+        let data_account_info = ctx.accounts.data.to_account_info();
+        let mut data = RawData::load(&data_account_info, ctx.accounts.signer.key)?;
+        repay_sponsor(
+            &mut data.header,
+            &data_account_info,
+            ctx.accounts
+                .sponsor
+                .as_ref()
+                .map(ToAccountInfo::to_account_info),
+            0,
+            &emitter!(ctx),
+        )?;
+
+        let lamports = data_account_info.lamports();
+        msg!(
+            "Closing data account {}, sending {} lamports to {}",
+            data_account_info.key(),
+            lamports,
+            ctx.accounts.recipient.key()
+        );
+
+        // the entry's rent goes back to whoever paid for it rather than
+        // `recipient`
+        unregister(
+            &mut ctx.accounts.directory,
+            &data_account_info.key(),
+            &ctx.accounts.signer.to_account_info(),
+            &emitter!(ctx),
+        )?;
+
+        let closed = ListClosed {
+            data: data_account_info.key(),
+            recipient: ctx.accounts.recipient.key(),
+            lamports,
+        };
+        emit!(closed);
+        emit_cpi!(closed);
+
+        data_account_info.sub_lamports(lamports)?;
+        ctx.accounts.recipient.add_lamports(lamports)?;
+        data_account_info.assign(&System::id());
+        data_account_info.realloc(0, false)?;
+
+        Ok(())
+    }
+
+    /// Sets the share of the rent reclaimed by `prune_expired` that goes to
+    /// whoever calls it, in basis points.
+    pub fn set_prune_reward(ctx: Context<Configure>, prune_reward_bps: u16) -> Result<()> {
+        require!(
+            prune_reward_bps <= Data::MAX_BPS,
+            ErrorCode::InvalidPruneReward
+        );
+        let data_account_info = ctx.accounts.data.to_account_info();
+        let mut data = RawData::load(&data_account_info, ctx.accounts.signer.key)?;
+        data.header.prune_reward_bps = prune_reward_bps;
+        data.save()
+    }
+
+    /// Removes the first `proofs.len()` expired entries, in list order, and
+    /// shrinks the account to fit the rest. Each proof empties the leaf of
+    /// its entry, and has to hold against the root the ones before it left.
+    /// Anyone can call it: the reclaimed rent repays the list's sponsor
+    /// first, and the rest goes to the authority, minus the
+    /// `prune_reward_bps` share paid to the caller.
+    pub fn prune_expired(ctx: Context<Prune>, proofs: Vec<Vec<[u8; 32]>>) -> Result<()> {
+        let data_account_info = ctx.accounts.data.to_account_info();
+        let mut data = RawData::open(&data_account_info)?;
+        require_keys_eq!(
+            data.header.authority,
+            ctx.accounts.authority.key(),
+            anchor_lang::error::ErrorCode::ConstraintHasOne
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        let expired = data.prune(now, &proofs)?;
+        require!(!expired.is_empty(), ErrorCode::NothingToPrune);
+
+        msg!("Pruning {} expired entries", expired.len());
+
+        for (index, entry) in &expired {
+            let removed = EntryRemoved {
+                data: data_account_info.key(),
+                entry: *entry,
+                index: *index as u32,
+            };
+            emit!(removed);
+            emit_cpi!(removed);
+        }
+
+        let curr_data_size = data_account_info.data_len();
+        let needed_len = Data::INIT_SIZE + data.len() * Entry::SIZE;
+        let mut reward = 0;
+
+        if needed_len < curr_data_size {
+            let rent_exempt_lamports = ctx.accounts.rent.minimum_balance(needed_len).max(1);
+            repay_sponsor(
+                &mut data.header,
+                &data_account_info,
+                ctx.accounts
+                    .sponsor
+                    .as_ref()
+                    .map(ToAccountInfo::to_account_info),
+                rent_exempt_lamports,
+                &emitter!(ctx),
+            )?;
+            let refund_lamports = data_account_info
+                .lamports()
+                .saturating_sub(rent_exempt_lamports);
+            reward = (refund_lamports as u128 * data.header.prune_reward_bps as u128
+                / Data::MAX_BPS as u128) as u64;
+
+            msg!(
+                "refund lamports: {}, caller reward: {}",
+                refund_lamports,
+                reward
+            );
+
+            // the caller is paid first, `shrink` sends the rest to the
+            // authority
+            data_account_info.sub_lamports(reward)?;
+            ctx.accounts.caller.add_lamports(reward)?;
+            shrink(
+                &data_account_info,
+                &ctx.accounts.authority.to_account_info(),
+                &ctx.accounts.rent,
+                needed_len,
+                &emitter!(ctx),
+            )?;
+        }
+
+        let pruned = EntriesPruned {
+            data: data_account_info.key(),
+            caller: ctx.accounts.caller.key(),
+            count: expired.len() as u32,
+            reward,
+        };
+        emit!(pruned);
+        emit_cpi!(pruned);
+
+        data.save()
+    }
+
+    /// Proposes `new_authority` as the next authority of the list, which
+    /// takes over once it calls `accept_authority`. Proposing the default
+    /// key cancels a pending transfer.
+    pub fn transfer_authority(ctx: Context<Configure>, new_authority: Pubkey) -> Result<()> {
+        msg!("Proposing {} as list authority", new_authority);
+        let data_account_info = ctx.accounts.data.to_account_info();
+        let mut data = RawData::load(&data_account_info, ctx.accounts.signer.key)?;
+        data.header.pending_authority = new_authority;
+        data.save()
+    }
+
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let data_account_info = ctx.accounts.data.to_account_info();
+        let mut data = RawData::open(&data_account_info)?;
+        require_keys_eq!(
+            data.header.pending_authority,
+            ctx.accounts.signer.key(),
+            ErrorCode::Unauthorized
+        );
+        require_keys_eq!(
+            data.header.authority,
+            ctx.accounts.previous_authority.key(),
+            anchor_lang::error::ErrorCode::ConstraintAddress
+        );
+        // only this program creates directories, always at the address of
+        // their authority
+        require_keys_eq!(
+            ctx.accounts.previous_directory.authority,
+            data.header.authority,
+            anchor_lang::error::ErrorCode::ConstraintSeeds
+        );
+
+        let transferred = AuthorityTransferred {
+            data: data_account_info.key(),
+            from: data.header.authority,
+            to: data.header.pending_authority,
+        };
+        emit!(transferred);
+        emit_cpi!(transferred);
+
+        data.header.authority = data.header.pending_authority;
+        data.header.pending_authority = Pubkey::default();
+        data.save()?;
+
+        // the list moves to the new authority's directory, which they pay
+        // for, and the previous authority gets their entry's rent back
+        if ctx.accounts.previous_authority.key() == ctx.accounts.signer.key() {
+            return Ok(());
+        }
+        let events = emitter!(ctx);
+        unregister(
+            &mut ctx.accounts.previous_directory,
+            &data_account_info.key(),
+            &ctx.accounts.previous_authority.to_account_info(),
+            &events,
+        )?;
+        register(
+            &ctx.accounts.directory,
+            ctx.bumps.directory,
+            &ctx.accounts.signer.to_account_info(),
+            &ctx.accounts.system_program,
+            data.header.name,
+            data_account_info.key(),
+            &events,
+        )?;
+
+        Ok(())
+    }
+
+    /// Makes the list permanently immutable, e.g. to publish a snapshot.
+    pub fn seal(ctx: Context<Configure>) -> Result<()> {
+        let data_account_info = ctx.accounts.data.to_account_info();
+        let mut data = RawData::load(&data_account_info, ctx.accounts.signer.key)?;
+        msg!("Sealing {} entries", data.len());
+        data.header.sealed = true;
+        data.save()?;
+
+        let sealed = Sealed {
+            data: data_account_info.key(),
+        };
+        emit!(sealed);
+        emit_cpi!(sealed);
+        Ok(())
+    }
+
+    /// Whether `entry` is in the list and hasn't expired.
+    pub fn contains(ctx: Context<Query>, entry: Pubkey) -> Result<bool> {
+        let now = Clock::get()?.unix_timestamp;
+        Ok(
+            raw::find_entry(&ctx.accounts.data, &entry)?
+                .is_some_and(|entry| !entry.is_expired(now)),
+        )
+    }
+
+    pub fn list(ctx: Context<List>, offset: u32, limit: u16) -> Result<ListSlice> {
+        let (entries, len) = raw::read_entries(
+            &ctx.accounts.data,
+            offset as usize,
+            (limit as usize).min(ListSlice::MAX_ENTRIES),
+        )?;
+
+        Ok(ListSlice {
+            total: len as u32,
+            entries,
+        })
+    }
+
+    /// Whether `entry` is in the list and hasn't expired. Reads the account
+    /// without deserializing the whole list, for other programs to call
+    /// through the `cpi` feature:
+    ///
+    /// ```ignore
+    /// let allowed = realloc_demo::cpi::is_member(
+    ///     CpiContext::new(realloc_demo_program, realloc_demo::cpi::accounts::IsMember { data }),
+    ///     user,
+    /// )?
+    /// .get();
+    /// ```
+    pub fn is_member(ctx: Context<IsMember>, entry: Pubkey) -> Result<bool> {
+        let now = Clock::get()?.unix_timestamp;
+        Ok(
+            raw::find_entry(&ctx.accounts.data, &entry)?
+                .is_some_and(|entry| !entry.is_expired(now)),
+        )
+    }
+
+    /// Checks that `entry` is in the list and hasn't expired, and that
+    /// `proof` shows its leaf against the list's merkle root, failing with
+    /// `EntryNotFound` or `InvalidProof`. Reads the account in place, like
+    /// `is_member`.
+    pub fn verify_membership(
+        ctx: Context<Verify>,
+        entry: Pubkey,
+        proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        let (header, _) = raw::read_header(&ctx.accounts.data)?;
+        let now = Clock::get()?.unix_timestamp;
+        let found = raw::find_entry(&ctx.accounts.data, &entry)?
+            .filter(|found| !found.is_expired(now))
+            .ok_or(ErrorCode::EntryNotFound)?;
+        require!(
+            merkle::verify(&header.merkle.root, &entry, found.leaf as usize, &proof),
+            ErrorCode::InvalidProof
+        );
+        Ok(())
+    }
+
+    pub fn migrate(ctx: Context<Migrate>) -> Result<()> {
+        migrate::migrate(ctx)
+    }
+
+    /// Copies up to `limit` entries of `source` from `cursor` on, replacing
+    /// the entries of the list when starting from 0. Returns the cursor to
+    /// call again with, or `None` once done. Fails with `SourceChanged` if
+    /// `source` changed since the copy started, see `CopyProgress`.
+    pub fn copy_from(ctx: Context<CopyEntries>, cursor: u32, limit: u16) -> Result<Option<u32>> {
+        copy::copy_from(ctx, cursor, limit)
+    }
+
+    /// Like `copy_from`, but keeps the entries of the list and skips those
+    /// of `source` whose key it already has. Reads fewer than `limit`
+    /// entries per call once an append list or a ring is long, see
+    /// `MAX_MERGE_COMPARES`.
+    pub fn merge_from(ctx: Context<CopyEntries>, cursor: u32, limit: u16) -> Result<Option<u32>> {
+        copy::merge_from(ctx, cursor, limit)
+    }
+
+    pub fn initialize_paged(ctx: Context<InitializePaged>, page_capacity: u16) -> Result<()> {
+        paged::initialize(ctx, page_capacity)
+    }
+
+    pub fn add_paged(ctx: Context<AddPaged>, entry: Pubkey) -> Result<()> {
+        paged::add(ctx, entry)
+    }
+
+    /// Like `list`, for paged lists. The pages holding the entries from
+    /// `offset` on are passed as remaining accounts, in order.
+    pub fn list_paged(ctx: Context<ListPaged>, offset: u64, limit: u16) -> Result<PagedSlice> {
+        paged::list(ctx, offset, limit)
+    }
+
+    pub fn initialize_map(ctx: Context<InitializeMap>, growth: GrowthPolicy) -> Result<()> {
+        map::initialize(ctx, growth)
+    }
+
+    pub fn upsert(ctx: Context<UpdateMap>, key: Pubkey, value: u64) -> Result<()> {
+        map::upsert(ctx, key, value)
+    }
+
+    /// Adds `amount` to the value of `key`, which starts at 0 if missing.
+    pub fn increment(ctx: Context<UpdateMap>, key: Pubkey, amount: u64) -> Result<()> {
+        map::increment(ctx, key, amount)
+    }
+
+    pub fn delete(ctx: Context<UpdateMap>, key: Pubkey) -> Result<()> {
+        map::delete(ctx, key)
+    }
+
+    pub fn get(ctx: Context<GetMap>, key: Pubkey) -> Result<Option<u64>> {
+        map::get(ctx, key)
     }
 }
 
+/// Reallocs `data` to `new_size` and tops it up to the rent-exempt minimum
+/// from `payer`, emitting `AccountResized` and `RentToppedUp`. Returns the
+/// lamports transferred.
+fn grow<'info>(
+    data: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    rent: &Rent,
+    new_size: usize,
+    events: &Emitter<'info>,
+) -> Result<u64> {
+    let old_size = data.data_len();
+    AccountInfo::realloc(data, new_size, false)?;
+
+    // if more lamports are needed, transfer them to the account
+    let rent_exempt_lamports = rent.minimum_balance(new_size).max(1);
+    let top_up_lamports = rent_exempt_lamports.saturating_sub(data.lamports());
+
+    msg!("top up lamports: {}", top_up_lamports);
+
+    if top_up_lamports > 0 {
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                system_program.clone(),
+                anchor_lang::system_program::Transfer {
+                    from: payer.clone(),
+                    to: data.clone(),
+                },
+            ),
+            top_up_lamports,
+        )?;
+    }
+
+    events.emit(&AccountResized {
+        data: data.key(),
+        old_size: old_size as u32,
+        new_size: new_size as u32,
+    })?;
+    if top_up_lamports > 0 {
+        events.emit(&RentToppedUp {
+            data: data.key(),
+            payer: payer.key(),
+            lamports: top_up_lamports,
+        })?;
+    }
+
+    Ok(top_up_lamports)
+}
+
+/// Reallocs `data` down to `new_size` and sends the lamports above the new
+/// rent-exempt minimum to `recipient`, emitting `AccountResized`. Returns
+/// the lamports refunded.
+fn shrink<'info>(
+    data: &AccountInfo<'info>,
+    recipient: &AccountInfo<'info>,
+    rent: &Rent,
+    new_size: usize,
+    events: &Emitter<'info>,
+) -> Result<u64> {
+    let old_size = data.data_len();
+    AccountInfo::realloc(data, new_size, false)?;
+
+    let rent_exempt_lamports = rent.minimum_balance(new_size).max(1);
+    let refund_lamports = data.lamports().saturating_sub(rent_exempt_lamports);
+
+    msg!("refund lamports: {}", refund_lamports);
+
+    if refund_lamports > 0 {
+        data.sub_lamports(refund_lamports)?;
+        recipient.add_lamports(refund_lamports)?;
+    }
+
+    events.emit(&AccountResized {
+        data: data.key(),
+        old_size: old_size as u32,
+        new_size: new_size as u32,
+    })?;
+
+    Ok(refund_lamports)
+}
+
+/// Repays `sponsor`, which must be the list's recorded sponsor, as much of
+/// what it paid into `data` as the account holds above `keep` lamports,
+/// emitting `SponsorRepaid`. Called before the account shrinks or closes, so
+/// that the sponsor is refunded ahead of the authority. Returns the
+/// lamports repaid.
+fn repay_sponsor<'info>(
+    header: &mut DataHeader,
+    data: &AccountInfo<'info>,
+    sponsor: Option<AccountInfo<'info>>,
+    keep: u64,
+    events: &Emitter<'info>,
+) -> Result<u64> {
+    let lamports = data.lamports().saturating_sub(keep).min(header.sponsored);
+    if lamports == 0 {
+        return Ok(0);
+    }
+    let sponsor = sponsor
+        .filter(|sponsor| *sponsor.key == header.sponsor)
+        .ok_or(ErrorCode::SponsorMismatch)?;
+
+    data.sub_lamports(lamports)?;
+    sponsor.add_lamports(lamports)?;
+    header.sponsored -= lamports;
+
+    events.emit(&SponsorRepaid {
+        data: data.key(),
+        sponsor: sponsor.key(),
+        lamports,
+    })?;
+    Ok(lamports)
+}
+
+/// Creates the PDA `account` signed for by `signer_seeds` with `space`
+/// bytes, owned by this program, the way `init` would. Unlike
+/// `create_account`, this works when lamports were already sent to the
+/// address: they count towards its rent and `payer` covers the rest.
+fn create_pda<'info>(
+    account: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    space: usize,
+    signer_seeds: &[&[u8]],
+) -> Result<()> {
+    let lamports = Rent::get()?
+        .minimum_balance(space)
+        .saturating_sub(account.lamports());
+    if lamports > 0 {
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                system_program.clone(),
+                anchor_lang::system_program::Transfer {
+                    from: payer.clone(),
+                    to: account.clone(),
+                },
+            ),
+            lamports,
+        )?;
+    }
+    anchor_lang::system_program::allocate(
+        CpiContext::new_with_signer(
+            system_program.clone(),
+            anchor_lang::system_program::Allocate {
+                account_to_allocate: account.clone(),
+            },
+            &[signer_seeds],
+        ),
+        space as u64,
+    )?;
+    anchor_lang::system_program::assign(
+        CpiContext::new_with_signer(
+            system_program.clone(),
+            anchor_lang::system_program::Assign {
+                account_to_assign: account.clone(),
+            },
+            &[signer_seeds],
+        ),
+        &crate::ID,
+    )
+}
+
 #[derive(Accounts)]
 pub struct List<'info> {
-    #[account()]
-    pub data: Account<'info, Data>,
+    /// CHECK: a `Data` account, read in place. Checked in
+    /// `raw::read_header`.
+    pub data: UncheckedAccount<'info>,
 }
 
+#[derive(Accounts)]
+pub struct Query<'info> {
+    /// CHECK: a `Data` account, read in place. Checked in
+    /// `raw::read_header`.
+    pub data: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct IsMember<'info> {
+    /// CHECK: a `Data` account, read in place. Checked in
+    /// `raw::read_header`.
+    pub data: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Verify<'info> {
+    /// CHECK: a `Data` account, read in place. Checked in
+    /// `raw::read_header`.
+    pub data: UncheckedAccount<'info>,
+}
+
+#[event_cpi]
 #[derive(Accounts)]
 pub struct Add<'info> {
+    /// CHECK: a `Data` account, accessed through `RawData` so that adding
+    /// doesn't deserialize the whole list. Checked in `RawData::load`.
     #[account(mut)]
-    pub data: Account<'info, Data>,
+    pub data: UncheckedAccount<'info>,
 
     #[account(mut)]
     pub signer: Signer<'info>,
+    /// Funds rent top-ups instead of `signer` when present, e.g. a relayer
+    /// sponsoring storage for the list authority. What it pays is recorded
+    /// as `DataHeader::sponsored` and repaid to it first when the list
+    /// shrinks or closes.
+    #[account(mut)]
+    pub payer: Option<Signer<'info>>,
     pub rent: Sysvar<'info, Rent>,
     pub system_program: Program<'info, System>,
 }
 
+impl<'info> Add<'info> {
+    pub fn rent_payer(&self) -> AccountInfo<'info> {
+        match &self.payer {
+            Some(payer) => payer.to_account_info(),
+            None => self.signer.to_account_info(),
+        }
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct Resize<'info> {
+    /// CHECK: a `Data` account, of which only the header is changed.
+    /// Checked in `RawData::load`.
+    #[account(mut)]
+    pub data: UncheckedAccount<'info>,
+
+    /// The list's authority, which pays for growing it and is refunded by
+    /// shrinking it.
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    /// CHECK: the list's sponsor, repaid out of the freed rent before
+    /// anyone else. Required while it is owed lamports, and checked against
+    /// `DataHeader::sponsor`.
+    #[account(mut)]
+    pub sponsor: Option<UncheckedAccount<'info>>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct Remove<'info> {
+    /// CHECK: a `Data` account, changed in place. Checked in
+    /// `RawData::load`.
+    #[account(mut)]
+    pub data: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    /// CHECK: the list's sponsor, repaid out of the freed rent before
+    /// anyone else. Required while it is owed lamports, and checked against
+    /// `DataHeader::sponsor`.
+    #[account(mut)]
+    pub sponsor: Option<UncheckedAccount<'info>>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct Close<'info> {
+    /// CHECK: checked by `RawData::load`, and closed without deserializing
+    /// the list
+    #[account(mut)]
+    pub data: UncheckedAccount<'info>,
+
+    /// Directory of the list's authority, which the list is taken out of.
+    #[account(
+        mut,
+        seeds = [b"directory".as_ref(), signer.key().as_ref()], bump = directory.bump
+    )]
+    pub directory: Account<'info, Directory>,
+
+    /// The list's authority, refunded the rent of its directory entry.
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    /// CHECK: receives the lamports of the closed account left after
+    /// repaying `sponsor`
+    #[account(mut)]
+    pub recipient: UncheckedAccount<'info>,
+    /// CHECK: the list's sponsor, repaid out of the freed rent before
+    /// anyone else. Required while it is owed lamports, and checked against
+    /// `DataHeader::sponsor`.
+    #[account(mut)]
+    pub sponsor: Option<UncheckedAccount<'info>>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct Configure<'info> {
+    /// CHECK: a `Data` account, of which only the header is changed.
+    /// Checked in `RawData::load`.
+    #[account(mut)]
+    pub data: UncheckedAccount<'info>,
+
+    pub signer: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    /// CHECK: a `Data` account, of which only the header is changed.
+    /// Checked in `RawData::open` and against `signer` in the instruction.
+    #[account(mut)]
+    pub data: UncheckedAccount<'info>,
+
+    /// Directory of the current authority, which the list is taken out of.
+    /// Checked against `data` in the instruction.
+    #[account(mut)]
+    pub previous_directory: Account<'info, Directory>,
+    /// CHECK: the current authority, refunded the rent of the list's entry
+    /// in their directory. Checked against `data` in the instruction.
+    #[account(mut)]
+    pub previous_authority: UncheckedAccount<'info>,
+
+    /// CHECK: the signer's `Directory`, created if they have none yet.
+    /// Checked in `directory::register`.
+    #[account(
+        mut,
+        seeds = [b"directory".as_ref(), signer.key().as_ref()], bump
+    )]
+    pub directory: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct Prune<'info> {
+    /// CHECK: a `Data` account, changed in place. Checked in
+    /// `RawData::open` and against `authority` in the instruction.
+    #[account(mut)]
+    pub data: UncheckedAccount<'info>,
+
+    /// CHECK: the list authority, which receives the reclaimed rent
+    #[account(mut)]
+    pub authority: UncheckedAccount<'info>,
+    /// Anyone; receives the prune reward.
+    #[account(mut)]
+    pub caller: Signer<'info>,
+    /// CHECK: the list's sponsor, repaid out of the freed rent before
+    /// anyone else. Required while it is owed lamports, and checked against
+    /// `DataHeader::sponsor`.
+    #[account(mut)]
+    pub sponsor: Option<UncheckedAccount<'info>>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[event_cpi]
 #[derive(Accounts)]
+#[instruction(name: String)]
 pub struct Initialize<'info> {
     #[account(
         init, 
-        seeds = [b"data".as_ref(), signer.key().as_ref()], bump,
+        seeds = [b"data".as_ref(), signer.key().as_ref(), name.as_bytes()], bump,
         payer = signer,
         space = Data::INIT_SIZE
     )]
     pub data: Account<'info, Data>,
+    /// CHECK: the signer's `Directory`, created along with their first
+    /// list. Checked in `directory::register`.
+    #[account(
+        mut,
+        seeds = [b"directory".as_ref(), signer.key().as_ref()], bump
+    )]
+    pub directory: UncheckedAccount<'info>,
 
     #[account(mut)]
     pub signer: Signer<'info>,
@@ -99,8 +1079,369 @@ pub struct Initialize<'info> {
 
 #[account]
 pub struct Data {
-    pub list: Vec<Pubkey>,
+    pub header: DataHeader,
+    pub list: Vec<Entry>,
+}
+
+/// The fields of `Data` in front of the list, which `RawData` reads and
+/// writes without touching the list.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, Debug)]
+pub struct DataHeader {
+    /// Layout version, bumped whenever the fields of `Data` change.
+    pub version: u8,
+    pub bump: u8,
+    pub mode: ListMode,
+    pub authority: Pubkey,
+    /// Wallet the account address is derived from, i.e. the first
+    /// authority.
+    pub creator: Pubkey,
+    /// Name the account address is derived from, see `name_seed`.
+    pub name: [u8; MAX_NAME_LEN],
+    /// Authority proposed by `transfer_authority`, or the default key if
+    /// there is none.
+    pub pending_authority: Pubkey,
+    /// Set by `seal`. A sealed list can't be changed in any way.
+    pub sealed: bool,
+    /// Number of entries the account has room for.
+    pub capacity: u32,
+    /// Number of entries stored.
+    pub count: u32,
+    /// Ring mode only: index in `list` of the oldest entry.
+    pub head: u32,
+    /// Ring mode only: index in `list` the next entry is written to.
+    pub tail: u32,
+    /// Share of the rent reclaimed by `prune_expired` paid to its caller,
+    /// in basis points.
+    pub prune_reward_bps: u16,
+    /// Merkle tree with a leaf for every key added to `list`, see `merkle`.
+    /// Removed keys leave an empty leaf behind.
+    pub merkle: MerkleTree,
+    /// Number of leaves of `merkle` in use, that is keys added since the
+    /// list was last empty. Each entry keeps its own as `Entry::leaf`.
+    pub merkle_len: u32,
+    pub growth: GrowthPolicy,
+    /// Account other than the authority that last paid for growing the
+    /// list, see `Add::payer`, or the default key if there is none.
+    pub sponsor: Pubkey,
+    /// Lamports `sponsor` has paid into the list and hasn't been repaid.
+    /// Shrinking or closing the list repays them before refunding anyone
+    /// else.
+    pub sponsored: u64,
+    /// The `copy_from` or `merge_from` into the list that is in progress,
+    /// if any.
+    pub copy: CopyProgress,
 }
+impl DataHeader {
+    /// Serialized size with the largest `mode` and `growth`.
+    pub const SIZE: usize = 1
+        + 1
+        + ListMode::SIZE
+        + 32
+        + 32
+        + MAX_NAME_LEN
+        + 32
+        + 1
+        + 4
+        + 4
+        + 4
+        + 4
+        + 2
+        + MerkleTree::SIZE
+        + 4
+        + GrowthPolicy::SIZE
+        + 32
+        + 8
+        + CopyProgress::SIZE;
+
+    /// Records `lamports` of rent growth paid by `payer`. Only one sponsor
+    /// is tracked at a time, so no one else can sponsor the list until it
+    /// has been repaid.
+    pub fn record_payment(&mut self, payer: &Pubkey, lamports: u64) -> Result<()> {
+        if lamports == 0 || *payer == self.authority {
+            return Ok(());
+        }
+        require!(
+            self.sponsored == 0 || self.sponsor == *payer,
+            ErrorCode::SponsorMismatch
+        );
+        self.sponsor = *payer;
+        self.sponsored += lamports;
+        Ok(())
+    }
+
+    /// Whether this is a ring that has reached its capacity.
+    pub fn is_full(&self) -> bool {
+        match self.mode {
+            ListMode::Ring { capacity } => self.count >= capacity,
+            _ => false,
+        }
+    }
+
+    /// Size an account of `data_len` bytes has to grow to before `add` can
+    /// store one more entry, or `None` if it has room. A full ring
+    /// overwrites its oldest entry instead of growing.
+    pub fn grown_size_for_add(&self, data_len: usize) -> Option<usize> {
+        let required_size = Data::INIT_SIZE + (self.count as usize + 1) * Entry::SIZE;
+        (required_size > data_len && !self.is_full()).then(|| {
+            let grown_size = self
+                .growth
+                .grown_size(Data::LAYOUT, data_len, required_size);
+            self.mode.clamp_size(grown_size)
+        })
+    }
+}
+
 impl Data {
-    pub const INIT_SIZE: usize = 8 + 4;
+    pub const VERSION: u8 = 2;
+    pub const INIT_SIZE: usize = 8 + DataHeader::SIZE + 4;
+    pub const MAX_BPS: u16 = 10_000;
+    pub const LAYOUT: Layout = Layout {
+        init_size: Self::INIT_SIZE,
+        item_size: Entry::SIZE,
+    };
+
+    /// Brings `capacity` and `count` in line with an account of `data_len`
+    /// bytes.
+    pub fn update_header(&mut self, data_len: usize) {
+        self.header.capacity = ((data_len - Self::INIT_SIZE) / Entry::SIZE) as u32;
+        self.header.count = self.list.len() as u32;
+    }
+
+    /// The `index`th entry, counting from the oldest one in ring mode.
+    pub fn get(&self, index: usize) -> Entry {
+        match self.header.mode {
+            ListMode::Ring { .. } => {
+                self.list[(self.header.head as usize + index) % self.list.len()]
+            }
+            _ => self.list[index],
+        }
+    }
+}
+
+/// A list entry along with who added it and when.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Entry {
+    pub key: Pubkey,
+    pub added_by: Pubkey,
+    pub timestamp: i64,
+    pub slot: u64,
+    /// Free-form label chosen by whoever added the entry.
+    pub tag: u8,
+    /// Unix timestamp from which the entry can be pruned, or 0 if it never
+    /// expires.
+    pub expires_at: i64,
+    /// Index of `key`'s leaf in the list's merkle tree, assigned when the
+    /// entry is written.
+    pub leaf: u32,
+}
+impl Entry {
+    pub const SIZE: usize = 32 + 32 + 8 + 8 + 1 + 8 + 4;
+
+    /// An entry for `key` stamped with the current clock.
+    pub fn new(key: Pubkey, added_by: Pubkey, tag: u8, expires_at: Option<i64>) -> Result<Self> {
+        let clock = Clock::get()?;
+        if let Some(expires_at) = expires_at {
+            require_gt!(expires_at, clock.unix_timestamp, ErrorCode::InvalidExpiry);
+        }
+
+        Ok(Self {
+            key,
+            added_by,
+            timestamp: clock.unix_timestamp,
+            slot: clock.slot,
+            tag,
+            expires_at: expires_at.unwrap_or(0),
+            leaf: 0,
+        })
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at != 0 && self.expires_at <= now
+    }
+}
+
+/// A window of entries returned by `list`, along with the total count so
+/// callers know when to stop paging.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct ListSlice {
+    pub total: u32,
+    pub entries: Vec<Entry>,
+}
+impl ListSlice {
+    /// Most entries that fit in return data next to the total and vec length.
+    pub const MAX_ENTRIES: usize = (MAX_RETURN_DATA - 4 - 4) / Entry::SIZE;
+}
+
+/// How entries are stored in the `Data` account.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ListMode {
+    /// Entries are kept in insertion order and may repeat.
+    Append,
+    /// Entries are kept sorted and unique.
+    Set,
+    /// At most `capacity` entries are kept in insertion order. Once full,
+    /// each new entry overwrites the oldest one.
+    Ring { capacity: u32 },
+}
+
+impl ListMode {
+    /// Serialized size of the largest variant.
+    pub const SIZE: usize = 1 + 4;
+
+    pub fn validate(&self) -> Result<()> {
+        if let ListMode::Ring { capacity } = self {
+            require!(*capacity > 0, ErrorCode::InvalidRingCapacity);
+        }
+        Ok(())
+    }
+
+    /// Caps an account size at what a full ring needs.
+    pub fn clamp_size(&self, size: usize) -> usize {
+        match self {
+            ListMode::Ring { capacity } => {
+                size.min(Data::INIT_SIZE + *capacity as usize * Entry::SIZE)
+            }
+            _ => size,
+        }
+    }
+}
+
+/// How the `Data` account is resized when entries are added or removed.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GrowthPolicy {
+    /// Grow by a fixed number of entries at a time.
+    Fixed { entries: u16 },
+    /// Double the capacity, capped at the per-instruction realloc limit.
+    Doubling,
+    /// Grow and shrink to exactly the space in use.
+    ExactFit,
+}
+
+impl GrowthPolicy {
+    /// Serialized size of the largest variant.
+    pub const SIZE: usize = 1 + 2;
+    /// Most entries a single realloc can add.
+    pub const MAX_STEP_ENTRIES: usize = MAX_PERMITTED_DATA_INCREASE / Entry::SIZE;
+
+    pub fn validate(&self) -> Result<()> {
+        if let GrowthPolicy::Fixed { entries } = self {
+            require!(
+                *entries > 0 && *entries as usize <= Self::MAX_STEP_ENTRIES,
+                ErrorCode::InvalidGrowthPolicy
+            );
+        }
+        Ok(())
+    }
+
+    /// Account size to grow to from `curr_size` so that at least
+    /// `required_size` bytes fit. Growth beyond `required_size` is capped at
+    /// the per-instruction realloc limit.
+    pub fn grown_size(&self, layout: Layout, curr_size: usize, required_size: usize) -> usize {
+        let target = match self {
+            GrowthPolicy::Fixed { entries } => {
+                let step = *entries as usize * layout.item_size;
+                curr_size + (required_size - curr_size).div_ceil(step) * step
+            }
+            GrowthPolicy::Doubling => {
+                let capacity = curr_size - layout.init_size;
+                curr_size + capacity.max(layout.item_size)
+            }
+            GrowthPolicy::ExactFit => required_size,
+        };
+        target
+            .min(curr_size + MAX_PERMITTED_DATA_INCREASE)
+            .max(required_size)
+    }
+
+    /// Account size to shrink to from `curr_size` when only `required_size`
+    /// bytes are in use, or `None` if the account should keep its size.
+    /// Fails if `required_size` doesn't fit in `curr_size`.
+    pub fn shrunk_size(
+        &self,
+        layout: Layout,
+        curr_size: usize,
+        required_size: usize,
+    ) -> Result<Option<usize>> {
+        let free = curr_size
+            .checked_sub(required_size)
+            .ok_or(ErrorCode::CorruptLayout)?;
+        Ok(match self {
+            // keep one step of slack so that add/remove at the boundary
+            // doesn't realloc back and forth
+            GrowthPolicy::Fixed { entries } => {
+                let step = *entries as usize * layout.item_size;
+                (free >= 2 * step).then(|| curr_size - step)
+            }
+            // halve once the list is down to a quarter of its capacity
+            GrowthPolicy::Doubling => {
+                let capacity = (curr_size - layout.init_size) / layout.item_size;
+                let len = (required_size - layout.init_size) / layout.item_size;
+                (capacity > 1 && len * 4 <= capacity)
+                    .then(|| layout.init_size + capacity.div_ceil(2) * layout.item_size)
+            }
+            GrowthPolicy::ExactFit => (free > 0).then_some(required_size),
+        })
+    }
+}
+
+/// Shape of an account that `GrowthPolicy` resizes: a fixed-size header
+/// followed by a vec of fixed-size items.
+#[derive(Clone, Copy, Debug)]
+pub struct Layout {
+    pub init_size: usize,
+    pub item_size: usize,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Entry not found in list")]
+    EntryNotFound,
+    #[msg("Signer is not the authority of this list")]
+    Unauthorized,
+    #[msg("Invalid growth policy")]
+    InvalidGrowthPolicy,
+    #[msg("Account would grow by more than the per-instruction realloc limit")]
+    ReallocLimitExceeded,
+    #[msg("Entry is already in the set")]
+    DuplicateEntry,
+    #[msg("Invalid page capacity")]
+    InvalidPageCapacity,
+    #[msg("Page account is not the expected page of this list")]
+    InvalidPage,
+    #[msg("Account layout version is not supported by this instruction")]
+    UnsupportedVersion,
+    #[msg("Account is already on the current layout")]
+    AlreadyMigrated,
+    #[msg("Ring capacity must be at least one entry")]
+    InvalidRingCapacity,
+    #[msg("Expiry must be in the future")]
+    InvalidExpiry,
+    #[msg("Prune reward can't exceed 10000 basis points")]
+    InvalidPruneReward,
+    #[msg("No entry has expired")]
+    NothingToPrune,
+    #[msg("Merkle proof doesn't match the list root")]
+    InvalidProof,
+    #[msg("Key not found in map")]
+    KeyNotFound,
+    #[msg("Value would overflow")]
+    ValueOverflow,
+    #[msg("List is sealed and can't be changed")]
+    ListSealed,
+    #[msg("List names are at most 32 bytes, without NUL bytes")]
+    InvalidName,
+    #[msg("Can't copy a list into itself")]
+    SameList,
+    #[msg("Account is smaller than the entries it holds")]
+    CorruptLayout,
+    #[msg("Source list changed since the copy started")]
+    SourceChanged,
+    #[msg("Limit must be at least one entry")]
+    InvalidLimit,
+    #[msg("Account is not the sponsor of this list")]
+    SponsorMismatch,
+    #[msg("Merkle tree has no leaves left, empty the list to start it over")]
+    MerkleTreeFull,
+    #[msg("Cursor doesn't continue the copy in progress from this source")]
+    CursorMismatch,
 }
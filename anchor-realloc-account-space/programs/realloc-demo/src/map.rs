@@ -0,0 +1,349 @@
+//! Realloc-backed maps from `Pubkey` to `u64`, e.g. per-wallet quotas or
+//! points.
+//!
+//! A `Map` PDA seeded by `[b"map", authority]` keeps its entries sorted by
+//! key so lookups are a binary search, done in place by `RawMap`. It grows and shrinks following its
+//! `GrowthPolicy` like a `Data` list does, with rent top-ups paid by and
+//! refunds sent to the authority.
+
+use anchor_lang::prelude::*;
+use anchor_lang::Discriminator;
+
+use crate::events::{emitter, KeyDeleted, ValueSet};
+use crate::raw::binary_search;
+use crate::{grow, shrink, ErrorCode, GrowthPolicy, Layout};
+
+pub fn initialize(ctx: Context<InitializeMap>, growth: GrowthPolicy) -> Result<()> {
+    growth.validate()?;
+
+    let map = &mut ctx.accounts.map;
+    map.header = MapHeader {
+        authority: ctx.accounts.signer.key(),
+        bump: ctx.bumps.map,
+        growth,
+    };
+    map.entries = Vec::new();
+
+    msg!("Map account: {}", map.key());
+
+    Ok(())
+}
+
+pub fn upsert(ctx: Context<UpdateMap>, key: Pubkey, value: u64) -> Result<()> {
+    msg!("Setting {} to {}", key, value);
+    write(ctx, key, |_| Ok(value))
+}
+
+pub fn increment(ctx: Context<UpdateMap>, key: Pubkey, amount: u64) -> Result<()> {
+    msg!("Incrementing {} by {}", key, amount);
+    write(ctx, key, |value| {
+        value
+            .checked_add(amount)
+            .ok_or_else(|| error!(ErrorCode::ValueOverflow))
+    })
+}
+
+/// Sets the value of `key` to `update` of its current value, inserting it
+/// with `update(0)` and growing the account if it isn't in the map yet.
+fn write(
+    ctx: Context<UpdateMap>,
+    key: Pubkey,
+    update: impl FnOnce(u64) -> Result<u64>,
+) -> Result<()> {
+    let map_account_info = ctx.accounts.map.to_account_info();
+    let mut map = RawMap::load(&map_account_info, ctx.accounts.signer.key)?;
+
+    let value = match map.position(&key)? {
+        Ok(index) => {
+            let value = update(map.value(index)?)?;
+            map.set_value(index, value)?;
+            value
+        }
+        Err(index) => {
+            let value = update(0)?;
+
+            let curr_data_size = map_account_info.data_len();
+            let required_size = Map::INIT_SIZE + (map.len() + 1) * MapEntry::SIZE;
+
+            if required_size > curr_data_size {
+                let needed_len =
+                    map.header
+                        .growth
+                        .grown_size(Map::LAYOUT, curr_data_size, required_size);
+                grow(
+                    &map_account_info,
+                    &ctx.accounts.signer.to_account_info(),
+                    &ctx.accounts.system_program.to_account_info(),
+                    &Rent::get()?,
+                    needed_len,
+                    &emitter!(ctx),
+                )?;
+            }
+
+            map.insert(index, &MapEntry { key, value })?;
+            value
+        }
+    };
+
+    let set = ValueSet {
+        map: map_account_info.key(),
+        key,
+        value,
+    };
+    emit!(set);
+    emit_cpi!(set);
+
+    Ok(())
+}
+
+pub fn delete(ctx: Context<UpdateMap>, key: Pubkey) -> Result<()> {
+    msg!("Deleting {}", key);
+    let map_account_info = ctx.accounts.map.to_account_info();
+    let mut map = RawMap::load(&map_account_info, ctx.accounts.signer.key)?;
+
+    let index = map.position(&key)?.map_err(|_| ErrorCode::KeyNotFound)?;
+    map.remove(index)?;
+
+    let deleted = KeyDeleted {
+        map: map_account_info.key(),
+        key,
+    };
+    emit!(deleted);
+    emit_cpi!(deleted);
+
+    let curr_data_size = map_account_info.data_len();
+    let required_size = Map::INIT_SIZE + map.len() * MapEntry::SIZE;
+
+    if let Some(needed_len) =
+        map.header
+            .growth
+            .shrunk_size(Map::LAYOUT, curr_data_size, required_size)?
+    {
+        shrink(
+            &map_account_info,
+            &ctx.accounts.signer.to_account_info(),
+            &Rent::get()?,
+            needed_len,
+            &emitter!(ctx),
+        )?;
+    }
+
+    Ok(())
+}
+
+pub fn get(ctx: Context<GetMap>, key: Pubkey) -> Result<Option<u64>> {
+    let map_account_info = ctx.accounts.map.to_account_info();
+    let map = RawMap::read(&map_account_info)?;
+    map.position(&key)?
+        .ok()
+        .map(|index| map.value(index))
+        .transpose()
+}
+
+#[derive(Accounts)]
+pub struct InitializeMap<'info> {
+    #[account(
+        init,
+        seeds = [b"map".as_ref(), signer.key().as_ref()], bump,
+        payer = signer,
+        space = Map::INIT_SIZE
+    )]
+    pub map: Account<'info, Map>,
+
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct UpdateMap<'info> {
+    /// CHECK: a `Map` account of `signer`, changed in place. Checked in
+    /// `RawMap::load`.
+    #[account(mut)]
+    pub map: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct GetMap<'info> {
+    /// CHECK: a `Map` account, read in place. Checked in `RawMap::read`.
+    pub map: UncheckedAccount<'info>,
+}
+
+#[account]
+pub struct Map {
+    pub header: MapHeader,
+    /// Sorted by key.
+    pub entries: Vec<MapEntry>,
+}
+impl Map {
+    pub const INIT_SIZE: usize = 8 + MapHeader::SIZE + 4;
+    pub const LAYOUT: Layout = Layout {
+        init_size: Self::INIT_SIZE,
+        item_size: MapEntry::SIZE,
+    };
+
+    /// Index of `key`, or the index it would be inserted at.
+    pub fn position(&self, key: &Pubkey) -> std::result::Result<usize, usize> {
+        self.entries.binary_search_by(|entry| entry.key.cmp(key))
+    }
+}
+
+/// Everything in a `Map` account but its entries.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, Debug)]
+pub struct MapHeader {
+    pub authority: Pubkey,
+    pub bump: u8,
+    pub growth: GrowthPolicy,
+}
+impl MapHeader {
+    /// Largest serialized size, with the largest growth policy.
+    pub const SIZE: usize = 32 + 1 + GrowthPolicy::SIZE;
+}
+
+/// A `Map` account accessed in place, like `RawData` does for lists:
+/// deserializing `Account<Map>` copies every entry onto the heap, which
+/// runs out at a few hundred keys.
+pub struct RawMap<'a, 'info> {
+    info: &'a AccountInfo<'info>,
+    pub header: MapHeader,
+    /// Offset of the entries' length prefix.
+    entries_offset: usize,
+    len: usize,
+}
+
+impl<'a, 'info> RawMap<'a, 'info> {
+    /// Decodes the header of the `Map` account `info` of `authority`,
+    /// checking the same things `UpdateMap` would in constraints.
+    pub fn load(info: &'a AccountInfo<'info>, authority: &Pubkey) -> Result<Self> {
+        let map = Self::read(info)?;
+        require_keys_eq!(map.header.authority, *authority, ErrorCode::Unauthorized);
+        let address = Pubkey::create_program_address(
+            &[b"map".as_ref(), authority.as_ref(), &[map.header.bump]],
+            &crate::ID,
+        )
+        .map_err(|_| anchor_lang::error::ErrorCode::ConstraintSeeds)?;
+        require_keys_eq!(
+            address,
+            *info.key,
+            anchor_lang::error::ErrorCode::ConstraintSeeds
+        );
+        Ok(map)
+    }
+
+    /// Decodes the header of the `Map` account `info`, checking its owner
+    /// and discriminator.
+    pub fn read(info: &'a AccountInfo<'info>) -> Result<Self> {
+        require_keys_eq!(
+            *info.owner,
+            crate::ID,
+            anchor_lang::error::ErrorCode::AccountOwnedByWrongProgram
+        );
+
+        let data = info.try_borrow_data()?;
+        require!(
+            data.len() >= Map::INIT_SIZE && data[..8] == Map::DISCRIMINATOR,
+            anchor_lang::error::ErrorCode::AccountDiscriminatorMismatch
+        );
+        let mut rest = &data[8..];
+        let header = MapHeader::deserialize(&mut rest)?;
+        let entries_offset = data.len() - rest.len();
+        let len = u32::from_le_bytes(data[entries_offset..][..4].try_into().unwrap()) as usize;
+        require!(
+            entries_offset + 4 + len * MapEntry::SIZE <= data.len(),
+            ErrorCode::CorruptLayout
+        );
+        drop(data);
+
+        Ok(Self {
+            info,
+            header,
+            entries_offset,
+            len,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Index of `key`, or the index it would be inserted at.
+    pub fn position(&self, key: &Pubkey) -> Result<std::result::Result<usize, usize>> {
+        let data = self.info.try_borrow_data()?;
+        let start = self.entries_offset + 4;
+        Ok(binary_search(
+            &data[start..start + self.len * MapEntry::SIZE],
+            MapEntry::SIZE,
+            key,
+        ))
+    }
+
+    pub fn value(&self, index: usize) -> Result<u64> {
+        let data = self.info.try_borrow_data()?;
+        let at = self.value_offset(index);
+        Ok(u64::from_le_bytes(data[at..at + 8].try_into().unwrap()))
+    }
+
+    pub fn set_value(&mut self, index: usize, value: u64) -> Result<()> {
+        let mut data = self.info.try_borrow_mut_data()?;
+        let at = self.value_offset(index);
+        data[at..at + 8].copy_from_slice(&value.to_le_bytes());
+        Ok(())
+    }
+
+    /// Writes `entry` at `index`, moving the entries from there on back by
+    /// one. The account must already have room for it.
+    pub fn insert(&mut self, index: usize, entry: &MapEntry) -> Result<()> {
+        let mut data = self.info.try_borrow_mut_data()?;
+        let start = self.entries_offset + 4;
+        let at = start + index * MapEntry::SIZE;
+        let end = start + self.len * MapEntry::SIZE;
+        require!(end + MapEntry::SIZE <= data.len(), ErrorCode::CorruptLayout);
+
+        data.copy_within(at..end, at + MapEntry::SIZE);
+        entry.serialize(&mut &mut data[at..at + MapEntry::SIZE])?;
+        self.len += 1;
+        self.write_len(&mut data);
+        Ok(())
+    }
+
+    /// Removes the entry at `index`, moving the ones after it forward.
+    pub fn remove(&mut self, index: usize) -> Result<()> {
+        let mut data = self.info.try_borrow_mut_data()?;
+        let start = self.entries_offset + 4;
+        let at = start + index * MapEntry::SIZE;
+        let end = start + self.len * MapEntry::SIZE;
+
+        data.copy_within(at + MapEntry::SIZE..end, at);
+        data[end - MapEntry::SIZE..end].fill(0);
+        self.len -= 1;
+        self.write_len(&mut data);
+        Ok(())
+    }
+
+    fn value_offset(&self, index: usize) -> usize {
+        self.entries_offset + 4 + index * MapEntry::SIZE + 32
+    }
+
+    fn write_len(&self, data: &mut [u8]) {
+        data[self.entries_offset..self.entries_offset + 4]
+            .copy_from_slice(&(self.len as u32).to_le_bytes());
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MapEntry {
+    pub key: Pubkey,
+    pub value: u64,
+}
+impl MapEntry {
+    pub const SIZE: usize = 32 + 8;
+}
@@ -0,0 +1,151 @@
+//! A running merkle commitment to the keys of a list.
+//!
+//! The tree has a fixed depth of `DEPTH`. Every key added to the list is
+//! appended as the next leaf, in insertion order whatever index it is stored
+//! at, and keeps that leaf as `Entry::leaf`. Removing an entry empties its
+//! leaf again, which takes a proof of it. Only the frontier (the last left
+//! node at each level) is stored, which is enough to append a leaf, or to
+//! replace one given its proof, in `DEPTH` hashes.
+//!
+//! A full ring overwrites its oldest entry without a proof, so the evicted
+//! key keeps its leaf. `verify_membership` checks the list as well as the
+//! proof, and off-chain provers take the evicted leaves from the
+//! `EntryAdded` events.
+//!
+//! - leaf: `sha256(0x00 || key)`
+//! - node: `sha256(0x01 || left || right)`
+//! - empty leaf: 32 zero bytes
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hashv;
+
+/// Depth of the tree. Leaves aren't reused, so this bounds how many keys
+/// can be added to a list before it has to be emptied, which starts the
+/// tree over.
+pub const DEPTH: usize = 20;
+
+pub fn leaf(key: &Pubkey) -> [u8; 32] {
+    hashv(&[&[0], key.as_ref()]).to_bytes()
+}
+
+pub fn node(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    hashv(&[&[1], left, right]).to_bytes()
+}
+
+/// Root of the tree in which leaf `index` is `leaf` and `proof` holds the
+/// siblings on its path, from the bottom up.
+fn root_from(leaf: [u8; 32], index: usize, proof: &[[u8; 32]]) -> [u8; 32] {
+    proof
+        .iter()
+        .enumerate()
+        .fold(leaf, |hash, (level, sibling)| {
+            if (index >> level) & 1 == 0 {
+                node(&hash, sibling)
+            } else {
+                node(sibling, &hash)
+            }
+        })
+}
+
+/// Whether `proof` leads from `key` as leaf `index` up to `root`.
+pub fn verify(root: &[u8; 32], key: &Pubkey, index: usize, proof: &[[u8; 32]]) -> bool {
+    proof.len() == DEPTH
+        && index < MerkleTree::CAPACITY
+        && root_from(leaf(key), index, proof) == *root
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MerkleTree {
+    pub root: [u8; 32],
+    /// At each level, the last node that is a left child.
+    pub frontier: [[u8; 32]; DEPTH],
+}
+
+impl MerkleTree {
+    pub const SIZE: usize = 32 + 32 * DEPTH;
+    /// Number of leaves the tree has room for, one short of `2^DEPTH`: the
+    /// frontier of a full tree would be all right nodes, which it doesn't
+    /// keep.
+    pub const CAPACITY: usize = (1 << DEPTH) - 1;
+
+    /// The tree with every leaf empty.
+    pub fn empty() -> Self {
+        let mut tree = Self {
+            root: [0; 32],
+            frontier: [[0; 32]; DEPTH],
+        };
+        tree.update_root(0);
+        tree
+    }
+
+    /// The tree over `keys`, in order.
+    pub fn build<'a>(keys: impl IntoIterator<Item = &'a Pubkey>) -> Self {
+        let mut tree = Self::empty();
+        tree.extend(0, keys.into_iter().copied());
+        tree
+    }
+
+    /// Appends `keys` to a tree of `len` leaves, recomputing the root once.
+    pub fn extend(&mut self, mut len: usize, keys: impl IntoIterator<Item = Pubkey>) {
+        for key in keys {
+            self.push_leaf(len, leaf(&key));
+            len += 1;
+        }
+        self.update_root(len);
+    }
+
+    /// Appends `key` to a tree of `len` leaves.
+    pub fn append(&mut self, len: usize, key: &Pubkey) {
+        self.push_leaf(len, leaf(key));
+        self.update_root(len + 1);
+    }
+
+    /// Empties leaf `index` of a tree of `len` leaves, which `proof` shows
+    /// to hold `key`. Returns whether the proof held; the tree is left as
+    /// it was if it didn't.
+    pub fn remove(&mut self, len: usize, index: usize, key: &Pubkey, proof: &[[u8; 32]]) -> bool {
+        if index >= len || !verify(&self.root, key, index, proof) {
+            return false;
+        }
+
+        let mut hash = [0; 32];
+        for (level, sibling) in proof.iter().enumerate() {
+            // the frontier holds the node on this path if it's the last
+            // complete left node of its level
+            if (len >> level) & 1 == 1 && index >> level == (len >> level) - 1 {
+                self.frontier[level] = hash;
+            }
+            hash = if (index >> level) & 1 == 0 {
+                node(&hash, sibling)
+            } else {
+                node(sibling, &hash)
+            };
+        }
+        self.root = hash;
+        true
+    }
+
+    fn push_leaf(&mut self, len: usize, mut hash: [u8; 32]) {
+        for (level, left) in self.frontier.iter_mut().enumerate() {
+            if (len >> level) & 1 == 0 {
+                *left = hash;
+                return;
+            }
+            hash = node(left, &hash);
+        }
+    }
+
+    fn update_root(&mut self, len: usize) {
+        let mut hash = [0; 32];
+        let mut empty = [0; 32];
+        for (level, left) in self.frontier.iter().enumerate() {
+            hash = if (len >> level) & 1 == 1 {
+                node(left, &hash)
+            } else {
+                node(&hash, &empty)
+            };
+            empty = node(&empty, &empty);
+        }
+        self.root = hash;
+    }
+}
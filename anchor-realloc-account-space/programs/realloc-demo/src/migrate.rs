@@ -0,0 +1,222 @@
+//! In-place migration of `Data` accounts written by the original program.
+//!
+//! The v1 layout is the bare `Data { list: Vec<Pubkey> }` the program
+//! started with, grown ten keys at a time: the length of the list follows
+//! the discriminator directly. The current layout starts with its version
+//! instead, so an account is only taken for v1 if it isn't a current list
+//! and its length prefix fits the account.
+//!
+//! Entries are much wider than bare keys, so migrating a long list can need
+//! more than the per-instruction realloc limit. `migrate` then only grows
+//! the account by that limit and has to be called again until the account
+//! is big enough to be rewritten.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::entrypoint::MAX_PERMITTED_DATA_INCREASE;
+use anchor_lang::Discriminator;
+
+use crate::copy::CopyProgress;
+use crate::directory::{register, MAX_NAME_LEN};
+use crate::events::emitter;
+use crate::merkle::MerkleTree;
+use crate::{grow, shrink, Data, DataHeader, Entry, ErrorCode, GrowthPolicy, ListMode};
+
+/// The `Data` layout the program started with.
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct DataV1 {
+    pub list: Vec<Pubkey>,
+}
+impl DataV1 {
+    pub const INIT_SIZE: usize = 8 + 4;
+    /// Keys a v1 list grew by at a time.
+    pub const GROWTH_ENTRIES: u16 = 10;
+
+    /// Number of keys in `data` if it holds a v1 list.
+    fn len(data: &[u8]) -> Option<usize> {
+        if data.len() < Self::INIT_SIZE || data[..8] != Data::DISCRIMINATOR {
+            return None;
+        }
+        let len = u32::from_le_bytes(data[8..Self::INIT_SIZE].try_into().unwrap()) as usize;
+        (Self::INIT_SIZE + len * 32 <= data.len()).then_some(len)
+    }
+}
+
+/// Whether `data` already holds the current unnamed list of `creator`.
+fn is_current(data: &[u8], creator: &Pubkey, bump: u8) -> bool {
+    if data.len() < Data::INIT_SIZE || data[..8] != Data::DISCRIMINATOR || data[8] != Data::VERSION
+    {
+        return false;
+    }
+    let Ok(header) = DataHeader::deserialize(&mut &data[8..]) else {
+        return false;
+    };
+    header.creator == *creator
+        && header.bump == bump
+        && header.name == [0; MAX_NAME_LEN]
+        && Data::INIT_SIZE + header.count as usize * Entry::SIZE <= data.len()
+}
+
+/// Rewrites a v1 account in the current layout, sized to fit exactly the
+/// entries it holds, and records it in its authority's directory. Growing
+/// further than the realloc limit allows takes several calls; every call
+/// but the last only grows the account.
+///
+/// v1 lists stored neither an authority nor entry metadata: the signer,
+/// whose address the list is derived from, becomes its authority and is
+/// recorded as having added every entry, at an unknown (zero) time. Each
+/// entry gets the leaf of its index in a merkle tree built over the list.
+pub fn migrate(ctx: Context<Migrate>) -> Result<()> {
+    let data_account_info = ctx.accounts.data.to_account_info();
+    require_keys_eq!(
+        *data_account_info.owner,
+        crate::ID,
+        ErrorCode::UnsupportedVersion
+    );
+
+    let signer = ctx.accounts.signer.key();
+    let len = {
+        let data = data_account_info.try_borrow_data()?;
+        require!(
+            !is_current(&data, &signer, ctx.bumps.data),
+            ErrorCode::AlreadyMigrated
+        );
+        DataV1::len(&data).ok_or(ErrorCode::UnsupportedVersion)?
+    };
+    require!(len <= MerkleTree::CAPACITY, ErrorCode::MerkleTreeFull);
+
+    let rent = Rent::get()?;
+    let curr_data_size = data_account_info.data_len();
+    let needed_len = Data::INIT_SIZE + len * Entry::SIZE;
+
+    if needed_len > curr_data_size + MAX_PERMITTED_DATA_INCREASE {
+        msg!(
+            "Growing to {} of {} bytes, call migrate again",
+            curr_data_size + MAX_PERMITTED_DATA_INCREASE,
+            needed_len
+        );
+        grow(
+            &data_account_info,
+            &ctx.accounts.signer.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            &rent,
+            curr_data_size + MAX_PERMITTED_DATA_INCREASE,
+            &emitter!(ctx),
+        )?;
+        return Ok(());
+    }
+
+    msg!("Migrating {} entries to v{}", len, Data::VERSION);
+
+    if needed_len > curr_data_size {
+        grow(
+            &data_account_info,
+            &ctx.accounts.signer.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            &rent,
+            needed_len,
+            &emitter!(ctx),
+        )?;
+    }
+
+    let mut header = DataHeader {
+        version: Data::VERSION,
+        bump: ctx.bumps.data,
+        mode: ListMode::Append,
+        authority: signer,
+        creator: signer,
+        name: [0; MAX_NAME_LEN],
+        pending_authority: Pubkey::default(),
+        sealed: false,
+        capacity: len as u32,
+        count: len as u32,
+        head: 0,
+        tail: 0,
+        prune_reward_bps: 0,
+        merkle: MerkleTree::empty(),
+        merkle_len: 0,
+        growth: GrowthPolicy::Fixed {
+            entries: DataV1::GROWTH_ENTRIES,
+        },
+        sponsor: Pubkey::default(),
+        sponsored: 0,
+        copy: CopyProgress::default(),
+    };
+    let list_offset = 8 + header.try_to_vec()?.len();
+
+    {
+        let mut data = data_account_info.try_borrow_mut_data()?;
+        let start = list_offset + 4;
+        // entries are wider than keys and start further in, so moving them
+        // from the back never overwrites a key that is still to be read
+        for i in (0..len).rev() {
+            let key = Pubkey::try_from(&data[DataV1::INIT_SIZE + i * 32..][..32]).unwrap();
+            let at = start + i * Entry::SIZE;
+            Entry {
+                key,
+                added_by: signer,
+                timestamp: 0,
+                slot: 0,
+                tag: 0,
+                expires_at: 0,
+                leaf: i as u32,
+            }
+            .serialize(&mut &mut data[at..at + Entry::SIZE])?;
+        }
+        data[start + len * Entry::SIZE..].fill(0);
+
+        header.merkle.extend(
+            0,
+            (0..len).map(|i| Pubkey::try_from(&data[start + i * Entry::SIZE..][..32]).unwrap()),
+        );
+        header.merkle_len = len as u32;
+
+        header.serialize(&mut &mut data[8..list_offset])?;
+        data[list_offset..start].copy_from_slice(&header.count.to_le_bytes());
+    }
+
+    // free space left over from the old layout is refunded
+    if needed_len < curr_data_size {
+        shrink(
+            &data_account_info,
+            &ctx.accounts.signer.to_account_info(),
+            &rent,
+            needed_len,
+            &emitter!(ctx),
+        )?;
+    }
+
+    register(
+        &ctx.accounts.directory,
+        ctx.bumps.directory,
+        &ctx.accounts.signer,
+        &ctx.accounts.system_program,
+        header.name,
+        data_account_info.key(),
+        &emitter!(ctx),
+    )?;
+
+    Ok(())
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct Migrate<'info> {
+    /// CHECK: a `Data` account in the v1 layout, which no longer
+    /// deserializes as `Data`. Checked in the handler.
+    #[account(
+        mut,
+        seeds = [b"data".as_ref(), signer.key().as_ref()], bump
+    )]
+    pub data: UncheckedAccount<'info>,
+    /// CHECK: the signer's `Directory`, created if they have none yet.
+    /// Checked in `directory::register`.
+    #[account(
+        mut,
+        seeds = [b"directory".as_ref(), signer.key().as_ref()], bump
+    )]
+    pub directory: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
@@ -0,0 +1,248 @@
+//! Paged lists for registries that outgrow a single account.
+//!
+//! A `PagedList` header tracks the total length and the number of pages.
+//! Entries live in fixed-capacity `DataPage` PDAs seeded by
+//! `[b"page", list, index]`, so a list is bounded only by how many pages
+//! the authority is willing to pay rent for. Pages are filled in order and
+//! a new one is created by `add_paged` when the last one is full.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::entrypoint::MAX_PERMITTED_DATA_INCREASE;
+use anchor_lang::solana_program::program::MAX_RETURN_DATA;
+use anchor_lang::Discriminator;
+
+use crate::events::EntryAdded;
+use crate::{create_pda, ErrorCode};
+
+pub fn initialize(ctx: Context<InitializePaged>, page_capacity: u16) -> Result<()> {
+    require!(
+        page_capacity > 0 && page_capacity as usize <= DataPage::MAX_CAPACITY,
+        ErrorCode::InvalidPageCapacity
+    );
+
+    let list = &mut ctx.accounts.list;
+    list.authority = ctx.accounts.signer.key();
+    list.bump = ctx.bumps.list;
+    list.page_capacity = page_capacity;
+    list.page_count = 0;
+    list.len = 0;
+
+    msg!("Paged list account: {}", list.key());
+
+    Ok(())
+}
+
+pub fn add(ctx: Context<AddPaged>, entry: Pubkey) -> Result<()> {
+    msg!("Adding new entry to paged list: {}", entry);
+    let list = &mut ctx.accounts.list;
+    let page_info = ctx.accounts.page.to_account_info();
+    let index = list.tail_page();
+
+    if index == list.page_count {
+        msg!("Creating page {}", index);
+        let space = DataPage::size(list.page_capacity);
+        let list_key = list.key();
+        let index_bytes = index.to_le_bytes();
+        let seeds = &[
+            b"page".as_ref(),
+            list_key.as_ref(),
+            index_bytes.as_ref(),
+            &[ctx.bumps.page],
+        ];
+
+        create_pda(
+            &page_info,
+            &ctx.accounts.signer.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            space,
+            seeds,
+        )?;
+
+        let page = DataPage {
+            list: list_key,
+            index,
+            entries: Vec::new(),
+        };
+        page.try_serialize(&mut &mut page_info.try_borrow_mut_data()?[..])?;
+
+        list.page_count += 1;
+    }
+
+    // written in place: deserializing the page would copy all its entries
+    // onto the heap just to append one
+    check_page(&page_info, &list.key(), index)?;
+    let mut page = page_info.try_borrow_mut_data()?;
+    let (len_prefix, entries) = page[DataPage::INIT_SIZE - 4..].split_at_mut(4);
+    let len = u32::from_le_bytes(len_prefix.try_into().unwrap());
+    entries
+        .get_mut(len as usize * 32..)
+        .and_then(|free| free.get_mut(..32))
+        .ok_or(ErrorCode::CorruptLayout)?
+        .copy_from_slice(entry.as_ref());
+    len_prefix.copy_from_slice(&(len + 1).to_le_bytes());
+    drop(page);
+
+    let added = EntryAdded {
+        data: list.key(),
+        entry,
+        index: list.len as u32,
+    };
+    emit!(added);
+    emit_cpi!(added);
+
+    list.len += 1;
+
+    Ok(())
+}
+
+pub fn list(ctx: Context<ListPaged>, offset: u64, limit: u16) -> Result<PagedSlice> {
+    let list = &ctx.accounts.list;
+    let capacity = list.page_capacity as u64;
+    let end = list
+        .len
+        .min(offset.saturating_add((limit as usize).min(PagedSlice::MAX_ENTRIES) as u64));
+
+    let mut pages = ctx.remaining_accounts.iter();
+    let mut entries = Vec::with_capacity(end.saturating_sub(offset) as usize);
+    let mut next = offset;
+    while next < end {
+        let index = (next / capacity) as u32;
+        let page_info = pages.next().ok_or(ErrorCode::InvalidPage)?;
+        check_page(page_info, &list.key(), index)?;
+
+        let page_end = end.min((index as u64 + 1) * capacity);
+        let start = (next % capacity) as usize * 32;
+        let stop = start + (page_end - next) as usize * 32;
+        let page = page_info.try_borrow_data()?;
+        let keys = page[DataPage::INIT_SIZE..]
+            .get(start..stop)
+            .ok_or(ErrorCode::CorruptLayout)?;
+        entries.extend(
+            keys.chunks_exact(32)
+                .map(|key| Pubkey::try_from(key).unwrap()),
+        );
+        next = page_end;
+    }
+
+    Ok(PagedSlice {
+        total: list.len,
+        entries,
+    })
+}
+
+/// Checks that `info` is the `index`th page of `list`, reading only the
+/// page header.
+fn check_page(info: &AccountInfo, list: &Pubkey, index: u32) -> Result<()> {
+    require_keys_eq!(*info.owner, crate::ID, ErrorCode::InvalidPage);
+    let data = info.try_borrow_data()?;
+    require!(
+        data.len() >= DataPage::INIT_SIZE
+            && data[..8] == DataPage::DISCRIMINATOR
+            && data[8..40] == list.to_bytes()
+            && data[40..44] == index.to_le_bytes(),
+        ErrorCode::InvalidPage
+    );
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct InitializePaged<'info> {
+    #[account(
+        init,
+        seeds = [b"paged".as_ref(), signer.key().as_ref()], bump,
+        payer = signer,
+        space = PagedList::SIZE
+    )]
+    pub list: Account<'info, PagedList>,
+
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct AddPaged<'info> {
+    #[account(
+        mut,
+        seeds = [b"paged".as_ref(), signer.key().as_ref()], bump = list.bump,
+        constraint = list.authority == signer.key() @ ErrorCode::Unauthorized
+    )]
+    pub list: Account<'info, PagedList>,
+
+    /// CHECK: the page the next entry goes to, created here if the previous
+    /// page is full. Checked in `check_page` and written in place.
+    #[account(
+        mut,
+        seeds = [b"page".as_ref(), list.key().as_ref(), list.tail_page().to_le_bytes().as_ref()],
+        bump
+    )]
+    pub page: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Reads entries of any paged list. The pages holding the requested entries
+/// are passed as remaining accounts, in order.
+#[derive(Accounts)]
+pub struct ListPaged<'info> {
+    pub list: Account<'info, PagedList>,
+}
+
+#[account]
+pub struct PagedList {
+    pub authority: Pubkey,
+    pub bump: u8,
+    pub page_capacity: u16,
+    pub page_count: u32,
+    pub len: u64,
+}
+impl PagedList {
+    pub const SIZE: usize = 8 + 32 + 1 + 2 + 4 + 8;
+
+    /// Index of the page the next entry goes to.
+    pub fn tail_page(&self) -> u32 {
+        (self.len / self.page_capacity as u64) as u32
+    }
+
+    pub fn page_address(list: &Pubkey, index: u32) -> Pubkey {
+        Pubkey::find_program_address(
+            &[
+                b"page".as_ref(),
+                list.as_ref(),
+                index.to_le_bytes().as_ref(),
+            ],
+            &crate::ID,
+        )
+        .0
+    }
+}
+
+#[account]
+pub struct DataPage {
+    pub list: Pubkey,
+    pub index: u32,
+    pub entries: Vec<Pubkey>,
+}
+impl DataPage {
+    pub const INIT_SIZE: usize = 8 + 32 + 4 + 4;
+    /// Largest page that can be created from within a program.
+    pub const MAX_CAPACITY: usize = (MAX_PERMITTED_DATA_INCREASE - Self::INIT_SIZE) / 32;
+
+    pub fn size(capacity: u16) -> usize {
+        Self::INIT_SIZE + capacity as usize * 32
+    }
+}
+
+/// A window of entries returned by `list_paged`, like `ListSlice`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct PagedSlice {
+    pub total: u64,
+    pub entries: Vec<Pubkey>,
+}
+impl PagedSlice {
+    /// Most entries that fit in return data next to the total and vec length.
+    pub const MAX_ENTRIES: usize = (MAX_RETURN_DATA - 8 - 4) / 32;
+}
@@ -0,0 +1,343 @@
+//! Raw access to `Data` accounts.
+//!
+//! Deserializing `Account<Data>` copies the whole list into a `Vec` and
+//! writes all of it back on exit, so every instruction would cost more the
+//! longer the list gets, and long lists wouldn't fit on the heap at all.
+//! `RawData` only decodes the header and changes entries and the vec length
+//! prefix straight in the account data.
+
+use anchor_lang::prelude::*;
+use anchor_lang::Discriminator;
+
+use crate::directory::name_seed;
+use crate::merkle::MerkleTree;
+use crate::{Data, DataHeader, Entry, ErrorCode, ListMode};
+
+pub struct RawData<'a, 'info> {
+    info: &'a AccountInfo<'info>,
+    pub header: DataHeader,
+    /// Offset of the list's length prefix.
+    list_offset: usize,
+}
+
+/// Decodes the header of the `Data` account `info`, checking its owner,
+/// discriminator and version. Returns it along with the offset of the
+/// list's length prefix.
+pub fn read_header(info: &AccountInfo) -> Result<(DataHeader, usize)> {
+    require_keys_eq!(
+        *info.owner,
+        crate::ID,
+        anchor_lang::error::ErrorCode::AccountOwnedByWrongProgram
+    );
+
+    let data = info.try_borrow_data()?;
+    require!(
+        data.len() >= Data::INIT_SIZE && data[..8] == Data::DISCRIMINATOR,
+        anchor_lang::error::ErrorCode::AccountDiscriminatorMismatch
+    );
+    let mut rest = &data[8..];
+    let header = DataHeader::deserialize(&mut rest)?;
+    let list_offset = data.len() - rest.len();
+
+    require_eq!(header.version, Data::VERSION, ErrorCode::UnsupportedVersion);
+
+    Ok((header, list_offset))
+}
+
+/// Looks `key` up in the `Data` account `info` without deserializing the
+/// whole list.
+pub fn find_entry(info: &AccountInfo, key: &Pubkey) -> Result<Option<Entry>> {
+    let (header, list_offset) = read_header(info)?;
+    let data = info.try_borrow_data()?;
+    let start = list_offset + 4;
+    let entries = &data[start..start + header.count as usize * Entry::SIZE];
+
+    let index = match header.mode {
+        ListMode::Set => binary_search(entries, Entry::SIZE, key).ok(),
+        _ => entries
+            .chunks_exact(Entry::SIZE)
+            .position(|entry| entry[..32] == key.to_bytes()),
+    };
+    index
+        .map(|index| Entry::try_from_slice(&entries[index * Entry::SIZE..][..Entry::SIZE]))
+        .transpose()
+        .map_err(Into::into)
+}
+
+/// Reads up to `limit` entries of the `Data` account `info` in list order,
+/// starting from the `start`th. Returns them along with the length of the
+/// list.
+pub fn read_entries(info: &AccountInfo, start: usize, limit: usize) -> Result<(Vec<Entry>, usize)> {
+    let (header, list_offset) = read_header(info)?;
+    let data = info.try_borrow_data()?;
+    let entries = &data[list_offset + 4..];
+    let len = header.count as usize;
+
+    let read = (start.min(len)..len.min(start.saturating_add(limit)))
+        .map(|i| {
+            let slot = match header.mode {
+                ListMode::Ring { .. } => (header.head as usize + i) % len,
+                _ => i,
+            };
+            Entry::try_from_slice(&entries[slot * Entry::SIZE..][..Entry::SIZE])
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    Ok((read, len))
+}
+
+impl<'a, 'info> RawData<'a, 'info> {
+    /// Decodes the header of the `Data` account `info` for a change by
+    /// `authority`, checking the same things the `Account<Data>` based
+    /// instructions check in constraints.
+    pub fn load(info: &'a AccountInfo<'info>, authority: &Pubkey) -> Result<Self> {
+        let data = Self::open(info)?;
+        require_keys_eq!(data.header.authority, *authority, ErrorCode::Unauthorized);
+        Ok(data)
+    }
+
+    /// Like `load`, for changes that aren't made by the authority, which the
+    /// caller checks itself.
+    pub fn open(info: &'a AccountInfo<'info>) -> Result<Self> {
+        let (header, list_offset) = read_header(info)?;
+        let address = Pubkey::create_program_address(
+            &[
+                b"data".as_ref(),
+                header.creator.as_ref(),
+                name_seed(&header.name),
+                &[header.bump],
+            ],
+            &crate::ID,
+        )
+        .map_err(|_| anchor_lang::error::ErrorCode::ConstraintSeeds)?;
+        require_keys_eq!(
+            address,
+            *info.key,
+            anchor_lang::error::ErrorCode::ConstraintSeeds
+        );
+        require!(!header.sealed, ErrorCode::ListSealed);
+
+        Ok(Self {
+            info,
+            header,
+            list_offset,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.header.count as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.header.count == 0
+    }
+
+    /// Whether `key` is in the list.
+    pub fn contains(&self, key: &Pubkey) -> Result<bool> {
+        let data = self.info.try_borrow_data()?;
+        let start = self.list_offset + 4;
+        let entries = &data[start..start + self.len() * Entry::SIZE];
+
+        Ok(match self.header.mode {
+            ListMode::Set => binary_search(entries, Entry::SIZE, key).is_ok(),
+            _ => entries
+                .chunks_exact(Entry::SIZE)
+                .any(|entry| entry[..32] == key.to_bytes()),
+        })
+    }
+
+    /// Drops every entry, keeping the space they took.
+    pub fn clear(&mut self) {
+        self.header.count = 0;
+        self.header.head = 0;
+        self.header.tail = 0;
+        self.header.merkle = MerkleTree::empty();
+        self.header.merkle_len = 0;
+    }
+
+    /// Writes `entry` into the list, at the end or at its sorted position in
+    /// set mode, and appends its key to the merkle tree as its `leaf`. A
+    /// full ring overwrites its oldest entry instead. The account must
+    /// already have room for it. Returns the index it was stored at,
+    /// counting from the oldest entry, and the entry it overwrote if any.
+    pub fn insert(&mut self, entry: &Entry) -> Result<(usize, Option<Pubkey>)> {
+        let entry = &Entry {
+            leaf: self.append_leaf(&entry.key)?,
+            ..*entry
+        };
+        let mut data = self.info.try_borrow_mut_data()?;
+        let start = self.list_offset + 4;
+        let end = start + self.len() * Entry::SIZE;
+
+        let index = match self.header.mode {
+            ListMode::Append => self.len(),
+            ListMode::Set => match binary_search(&data[start..end], Entry::SIZE, &entry.key) {
+                Ok(_) => return err!(ErrorCode::DuplicateEntry),
+                Err(index) => index,
+            },
+            ListMode::Ring { capacity } => {
+                let at = start + self.header.tail as usize * Entry::SIZE;
+                self.header.tail = (self.header.tail + 1) % capacity;
+
+                // a ring that isn't full yet starts at 0, so this appends
+                if self.header.count < capacity {
+                    entry.serialize(&mut &mut data[at..at + Entry::SIZE])?;
+                    self.header.count += 1;
+                    return Ok((self.len() - 1, None));
+                }
+
+                // the key leads the entry. Its leaf stays in the tree, as
+                // emptying it would take a proof, see `merkle`
+                let evicted = Pubkey::try_from(&data[at..at + 32]).unwrap();
+                entry.serialize(&mut &mut data[at..at + Entry::SIZE])?;
+                self.header.head = self.header.tail;
+                return Ok((self.len() - 1, Some(evicted)));
+            }
+        };
+
+        let at = start + index * Entry::SIZE;
+        data.copy_within(at..end, at + Entry::SIZE);
+        entry.serialize(&mut &mut data[at..at + Entry::SIZE])?;
+        self.header.count += 1;
+
+        Ok((index, None))
+    }
+
+    /// Removes `key` from the list and empties its leaf, which `proof` has
+    /// to show it holds. Returns the index it was at. A ring is unrolled
+    /// into insertion order first.
+    pub fn remove(&mut self, key: &Pubkey, proof: &[[u8; 32]]) -> Result<usize> {
+        self.unroll()?;
+        let mut data = self.info.try_borrow_mut_data()?;
+        let start = self.list_offset + 4;
+        let end = start + self.len() * Entry::SIZE;
+
+        let index = match self.header.mode {
+            ListMode::Set => binary_search(&data[start..end], Entry::SIZE, key).ok(),
+            _ => data[start..end]
+                .chunks_exact(Entry::SIZE)
+                .position(|entry| entry[..32] == key.to_bytes()),
+        }
+        .ok_or(ErrorCode::EntryNotFound)?;
+
+        let at = start + index * Entry::SIZE;
+        self.remove_leaf(&Entry::try_from_slice(&data[at..at + Entry::SIZE])?, proof)?;
+        data.copy_within(at + Entry::SIZE..end, at);
+        data[end - Entry::SIZE..end].fill(0);
+        self.header.count -= 1;
+        self.after_removal();
+
+        Ok(index)
+    }
+
+    /// Removes the first `proofs.len()` entries expired at `now`, in list
+    /// order, emptying the leaf of each with its proof. Each proof has to
+    /// hold for the tree the ones before it left. Returns every removed
+    /// entry with the index it was at when it was removed.
+    pub fn prune(&mut self, now: i64, proofs: &[Vec<[u8; 32]>]) -> Result<Vec<(usize, Pubkey)>> {
+        self.unroll()?;
+        let mut data = self.info.try_borrow_mut_data()?;
+        let start = self.list_offset + 4;
+
+        let mut proofs = proofs.iter();
+        let mut pruned = Vec::new();
+        let mut kept = 0;
+        for i in 0..self.len() {
+            let at = start + i * Entry::SIZE;
+            let entry = Entry::try_from_slice(&data[at..at + Entry::SIZE])?;
+            let proof = entry.is_expired(now).then(|| proofs.next()).flatten();
+            if let Some(proof) = proof {
+                self.remove_leaf(&entry, proof)?;
+                pruned.push((kept, entry.key));
+            } else {
+                data.copy_within(at..at + Entry::SIZE, start + kept * Entry::SIZE);
+                kept += 1;
+            }
+        }
+
+        data[start + kept * Entry::SIZE..start + self.len() * Entry::SIZE].fill(0);
+        self.header.count = kept as u32;
+        if !pruned.is_empty() {
+            self.after_removal();
+        }
+
+        Ok(pruned)
+    }
+
+    /// Rotates a ring so that its oldest entry comes first.
+    fn unroll(&mut self) -> Result<()> {
+        if let ListMode::Ring { .. } = self.header.mode {
+            let mut data = self.info.try_borrow_mut_data()?;
+            let start = self.list_offset + 4;
+            data[start..start + self.len() * Entry::SIZE]
+                .rotate_left(self.header.head as usize * Entry::SIZE);
+            self.header.head = 0;
+        }
+        Ok(())
+    }
+
+    fn after_removal(&mut self) {
+        if let ListMode::Ring { capacity } = self.header.mode {
+            self.header.tail = self.header.count % capacity;
+        }
+    }
+
+    /// Appends `key` to the merkle tree, returning its leaf. Adding to an
+    /// empty list starts the tree over, as none of its leaves are in use.
+    fn append_leaf(&mut self, key: &Pubkey) -> Result<u32> {
+        if self.is_empty() && self.header.merkle_len > 0 {
+            self.header.merkle = MerkleTree::empty();
+            self.header.merkle_len = 0;
+        }
+
+        let leaf = self.header.merkle_len;
+        require!(
+            (leaf as usize) < MerkleTree::CAPACITY,
+            ErrorCode::MerkleTreeFull
+        );
+        self.header.merkle.append(leaf as usize, key);
+        self.header.merkle_len += 1;
+        Ok(leaf)
+    }
+
+    /// Empties the leaf of `entry`, which `proof` has to show it holds.
+    fn remove_leaf(&mut self, entry: &Entry, proof: &[[u8; 32]]) -> Result<()> {
+        let len = self.header.merkle_len as usize;
+        require!(
+            self.header
+                .merkle
+                .remove(len, entry.leaf as usize, &entry.key, proof),
+            ErrorCode::InvalidProof
+        );
+        Ok(())
+    }
+
+    /// Writes the header and the list length prefix back to the account.
+    pub fn save(&mut self) -> Result<()> {
+        let mut data = self.info.try_borrow_mut_data()?;
+        self.header.capacity = ((data.len() - Data::INIT_SIZE) / Entry::SIZE) as u32;
+        self.header.serialize(&mut &mut data[8..self.list_offset])?;
+        data[self.list_offset..self.list_offset + 4]
+            .copy_from_slice(&self.header.count.to_le_bytes());
+        Ok(())
+    }
+}
+
+/// Binary search by key over serialized items of `item_size` bytes, sorted
+/// by the key they start with and laid out back to back.
+pub(crate) fn binary_search(
+    items: &[u8],
+    item_size: usize,
+    key: &Pubkey,
+) -> std::result::Result<usize, usize> {
+    let (mut low, mut high) = (0, items.len() / item_size);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        let at = mid * item_size;
+        match items[at..at + 32].cmp(key.as_ref()) {
+            std::cmp::Ordering::Less => low = mid + 1,
+            std::cmp::Ordering::Greater => high = mid,
+            std::cmp::Ordering::Equal => return Ok(mid),
+        }
+    }
+    Err(low)
+}
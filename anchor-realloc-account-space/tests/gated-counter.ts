@@ -0,0 +1,102 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { GatedCounter } from "../target/types/gated_counter";
+import { ReallocDemo } from "../target/types/realloc_demo";
+import { PublicKey } from "@solana/web3.js";
+import { SYSVAR_RENT_PUBKEY } from "@solana/web3.js";
+import { assert } from "chai";
+
+describe("gated-counter", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.GatedCounter as Program<GatedCounter>;
+  const reallocDemo = anchor.workspace.ReallocDemo as Program<ReallocDemo>;
+  const eventAccounts = {
+    eventAuthority: PublicKey.findProgramAddressSync(
+      [Buffer.from("__event_authority")],
+      reallocDemo.programId
+    )[0],
+    program: reallocDemo.programId,
+  };
+
+  const fundedKeypair = async () => {
+    const keypair = anchor.web3.Keypair.generate();
+    await provider.connection.confirmTransaction(
+      await provider.connection.requestAirdrop(
+        keypair.publicKey,
+        anchor.web3.LAMPORTS_PER_SOL
+      )
+    );
+    return keypair;
+  };
+
+  it("Only lets allowlisted wallets increment", async () => {
+    const authority = await fundedKeypair();
+    const [allowlist] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("data"),
+        authority.publicKey.toBuffer(),
+        Buffer.from("allowlist"),
+      ],
+      reallocDemo.programId
+    );
+    const [directory] = PublicKey.findProgramAddressSync(
+      [Buffer.from("directory"), authority.publicKey.toBuffer()],
+      reallocDemo.programId
+    );
+    await reallocDemo.methods
+      .initialize("allowlist", { exactFit: {} }, { set: {} })
+      .accounts({
+        data: allowlist,
+        directory,
+        signer: authority.publicKey,
+        ...eventAccounts,
+      })
+      .signers([authority])
+      .rpc();
+
+    const member = anchor.web3.Keypair.generate();
+    await reallocDemo.methods
+      .add(member.publicKey, 0, null)
+      .accounts({
+        data: allowlist,
+        signer: authority.publicKey,
+        rent: SYSVAR_RENT_PUBKEY,
+        ...eventAccounts,
+      })
+      .signers([authority])
+      .rpc();
+
+    const [counter] = PublicKey.findProgramAddressSync(
+      [Buffer.from("counter"), allowlist.toBuffer()],
+      program.programId
+    );
+    await program.methods
+      .initialize()
+      .accounts({ counter, allowlist, signer: provider.wallet.publicKey })
+      .rpc();
+
+    const increment = (user: anchor.web3.Keypair) =>
+      program.methods
+        .increment()
+        .accounts({
+          counter,
+          allowlist,
+          user: user.publicKey,
+          reallocDemoProgram: reallocDemo.programId,
+        })
+        .signers([user])
+        .rpc();
+
+    await increment(member);
+    assert.equal((await program.account.counter.fetch(counter)).count, 1);
+
+    try {
+      await increment(anchor.web3.Keypair.generate());
+      assert.fail("a wallet off the allowlist should have been rejected");
+    } catch (e) {
+      assert.equal(e.error?.errorCode?.code, "NotAllowed");
+    }
+  });
+});
@@ -4,6 +4,8 @@ import { ReallocDemo } from "../target/types/realloc_demo";
 import { PublicKey } from "@solana/web3.js";
 import { SYSVAR_RENT_PUBKEY } from "@solana/web3.js";
 import { assert } from "chai";
+import { createHash } from "crypto";
+import * as fs from "fs";
 
 describe("realloc-demo", () => {
   // Configure the client to use the local cluster.
@@ -19,32 +21,157 @@ describe("realloc-demo", () => {
   );
   console.log("dataPda", dataPda.toBase58());
 
+  // accounts required by instructions that emit events via self-CPI
+  const eventAccounts = {
+    eventAuthority: PublicKey.findProgramAddressSync(
+      [Buffer.from("__event_authority")],
+      program.programId
+    )[0],
+    program: program.programId,
+  };
+
+  // discriminator + version + bump + mode + authority + creator + name
+  // + pending authority + sealed + capacity + count + head + tail + prune
+  // reward + merkle root and frontier + merkle length + growth policy
+  // + sponsor + sponsored lamports + copy progress + vec length
+  const INIT_SIZE =
+    8 + 1 + 1 + 5 + 32 + 32 + 32 + 32 + 1 + 4 + 4 + 4 + 4 + 2 + 32 + 20 * 32 +
+    4 + 3 + 32 + 8 + 32 + 4 + 4 + 32 + 4;
+  // key + added by + timestamp + slot + tag + expiry + merkle leaf
+  const ENTRY_SIZE = 32 + 32 + 8 + 8 + 1 + 8 + 4;
+
+  const fundedKeypair = async () => {
+    const keypair = anchor.web3.Keypair.generate();
+    await provider.connection.confirmTransaction(
+      await provider.connection.requestAirdrop(
+        keypair.publicKey,
+        anchor.web3.LAMPORTS_PER_SOL
+      )
+    );
+    return keypair;
+  };
+
+  const dataPdaOf = (creator: PublicKey, name = "") =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("data"), creator.toBuffer(), Buffer.from(name)],
+      program.programId
+    )[0];
+
+  const directoryPdaOf = (authority: PublicKey) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("directory"), authority.toBuffer()],
+      program.programId
+    )[0];
+
+  // creates the list `name` of `authority`, a new funded wallet unless given
+  const createList = async (
+    growth,
+    mode,
+    name = "",
+    authority?: anchor.web3.Keypair
+  ) => {
+    authority ??= await fundedKeypair();
+    const data = dataPdaOf(authority.publicKey, name);
+    await program.methods
+      .initialize(name, growth, mode)
+      .accounts({
+        data,
+        directory: directoryPdaOf(authority.publicKey),
+        signer: authority.publicKey,
+        ...eventAccounts,
+      })
+      .signers([authority])
+      .rpc();
+    return { authority, data };
+  };
+
+  // accounts of the instructions that add, remove or resize entries
+  const listAccounts = (data: PublicKey, signer: PublicKey) => ({
+    data,
+    signer,
+    rent: SYSVAR_RENT_PUBKEY,
+    ...eventAccounts,
+  });
+
+  // names are stored padded with zeros
+  const nameOf = (name: number[]) =>
+    Buffer.from(name).toString().replace(/\0+$/, "");
+
+  // mirrors programs/realloc-demo/src/merkle.rs
+  const MERKLE_DEPTH = 20;
+  const sha256 = (...parts: Buffer[]) =>
+    createHash("sha256").update(Buffer.concat(parts)).digest();
+  const merkleLeaf = (key: PublicKey) =>
+    sha256(Buffer.from([0]), key.toBuffer());
+  const merkleNode = (left: Buffer, right: Buffer) =>
+    sha256(Buffer.from([1]), left, right);
+
+  // root of the tree over `leaves` and the proof for the leaf at `index`
+  const merkleProof = (leaves: Buffer[], index: number) => {
+    let level = leaves;
+    let empty = Buffer.alloc(32);
+    const proof = [];
+    for (let depth = 0; depth < MERKLE_DEPTH; depth++) {
+      proof.push([...(level[index ^ 1] ?? empty)]);
+      const next = [];
+      for (let i = 0; i < level.length; i += 2) {
+        next.push(merkleNode(level[i], level[i + 1] ?? empty));
+      }
+      level = next;
+      index >>= 1;
+      empty = merkleNode(empty, empty);
+    }
+    return { root: level[0] ?? empty, proof };
+  };
+
+  // leaves of the tree of `data`, empty where a key was removed or
+  // evicted from a ring
+  const merkleLeaves = async (data: PublicKey) => {
+    const { header, list } = await program.account.data.fetch(data);
+    const leaves = Array.from({ length: header.merkleLen }, () =>
+      Buffer.alloc(32)
+    );
+    for (const entry of list) {
+      leaves[entry.leaf] = merkleLeaf(entry.key);
+    }
+    return leaves;
+  };
+
+  // proof of `key` against the current root of `data`
+  const proofOf = async (data: PublicKey, key: PublicKey) => {
+    const { list } = await program.account.data.fetch(data);
+    const { leaf } = list.find((entry) => entry.key.equals(key));
+    return merkleProof(await merkleLeaves(data), leaf).proof;
+  };
+
   it("Is initialized!", async () => {
     const tx = await program.methods
-      .initialize()
-      .accounts({ data: dataPda, signer: wallet.publicKey })
+      .initialize("", { fixed: { entries: 10 } }, { append: {} })
+      .accounts({
+        data: dataPda,
+        directory: directoryPdaOf(wallet.publicKey),
+        signer: wallet.publicKey,
+        ...eventAccounts,
+      })
       .rpc();
     console.log("Your transaction signature", tx);
+
+    const data = await program.account.data.fetch(dataPda);
+    assert.equal(data.header.version, 2);
+    assert.equal(data.header.authority.toBase58(), wallet.publicKey.toBase58());
+    assert.equal(data.header.bump, dataBump);
   });
 
   it("Add data", async () => {
     try {
       await program.methods
-        .add(dataPda)
-        .accounts({
-          data: dataPda,
-          signer: wallet.publicKey,
-          rent: SYSVAR_RENT_PUBKEY,
-        })
+        .add(dataPda, 0, null)
+        .accounts(listAccounts(dataPda, wallet.publicKey))
         .rpc();
 
       await program.methods
-        .add(wallet.publicKey)
-        .accounts({
-          data: dataPda,
-          signer: wallet.publicKey,
-          rent: SYSVAR_RENT_PUBKEY,
-        })
+        .add(wallet.publicKey, 7, null)
+        .accounts(listAccounts(dataPda, wallet.publicKey))
         .rpc();
     } catch (e) {
       console.error(e);
@@ -54,7 +181,1258 @@ describe("realloc-demo", () => {
     const { list } = await program.account.data.fetch(dataPda);
     console.log("list", list);
     assert.equal(list.length, 2);
-    assert.equal(list[0].toBase58(), dataPda.toBase58());
-    assert.equal(list[1].toBase58(), wallet.publicKey.toBase58());
+    assert.equal(list[0].key.toBase58(), dataPda.toBase58());
+    assert.equal(list[1].key.toBase58(), wallet.publicKey.toBase58());
+    assert.equal(list[1].addedBy.toBase58(), wallet.publicKey.toBase58());
+    assert.equal(list[1].tag, 7);
+    assert.isAbove(list[1].timestamp.toNumber(), 0);
+    assert.isAtLeast(list[1].slot.toNumber(), list[0].slot.toNumber());
+  });
+
+  it("Remove data", async () => {
+    await program.methods
+      .remove(dataPda, await proofOf(dataPda, dataPda))
+      .accounts(listAccounts(dataPda, wallet.publicKey))
+      .rpc();
+
+    const { list } = await program.account.data.fetch(dataPda);
+    assert.equal(list.length, 1);
+    assert.equal(list[0].key.toBase58(), wallet.publicKey.toBase58());
+  });
+
+  it("Shrink and refund rent on remove", async () => {
+    const entries = Array.from(
+      { length: 20 },
+      () => anchor.web3.Keypair.generate().publicKey
+    );
+    for (const entry of entries) {
+      await program.methods
+        .add(entry, 0, null)
+        .accounts(listAccounts(dataPda, wallet.publicKey))
+        .rpc();
+    }
+
+    const before = await provider.connection.getAccountInfo(dataPda);
+    for (const entry of entries) {
+      await program.methods
+        .remove(entry, await proofOf(dataPda, entry))
+        .accounts(listAccounts(dataPda, wallet.publicKey))
+        .rpc();
+    }
+    const after = await provider.connection.getAccountInfo(dataPda);
+
+    assert.isBelow(after.data.length, before.data.length);
+    assert.equal(
+      after.lamports,
+      await provider.connection.getMinimumBalanceForRentExemption(
+        after.data.length
+      )
+    );
+
+    const { list } = await program.account.data.fetch(dataPda);
+    assert.equal(list.length, 1);
+  });
+
+  it("Rejects add from a wallet that doesn't own the list", async () => {
+    const intruder = await fundedKeypair();
+
+    try {
+      await program.methods
+        .add(intruder.publicKey, 0, null)
+        .accounts(listAccounts(dataPda, intruder.publicKey))
+        .signers([intruder])
+        .rpc();
+      assert.fail("add should have been rejected");
+    } catch (e) {
+      assert.equal(e.error?.errorCode?.code, "Unauthorized");
+    }
+  });
+
+  it("Grows according to the growth policy", async () => {
+    const sizesAfterAdds = async (growth, adds: number) => {
+      const { authority, data } = await createList(growth, { append: {} });
+
+      const sizes = [];
+      for (let i = 0; i < adds; i++) {
+        await program.methods
+          .add(anchor.web3.Keypair.generate().publicKey, 0, null)
+          .accounts(listAccounts(data, authority.publicKey))
+          .signers([authority])
+          .rpc();
+        const account = await provider.connection.getAccountInfo(data);
+        sizes.push(account.data.length);
+      }
+      return sizes;
+    };
+
+    const entries = (n: number) => INIT_SIZE + n * ENTRY_SIZE;
+
+    assert.deepEqual(await sizesAfterAdds({ exactFit: {} }, 3), [
+      entries(1),
+      entries(2),
+      entries(3),
+    ]);
+    assert.deepEqual(await sizesAfterAdds({ doubling: {} }, 5), [
+      entries(1),
+      entries(2),
+      entries(4),
+      entries(4),
+      entries(8),
+    ]);
+    assert.deepEqual(await sizesAfterAdds({ fixed: { entries: 2 } }, 3), [
+      entries(2),
+      entries(2),
+      entries(4),
+    ]);
+  });
+
+  it("Rejects an empty fixed growth step", async () => {
+    try {
+      await createList({ fixed: { entries: 0 } }, { append: {} });
+      assert.fail("initialize should have been rejected");
+    } catch (e) {
+      assert.equal(e.error?.errorCode?.code, "InvalidGrowthPolicy");
+    }
+  });
+
+  it("Adds a batch with a single realloc", async () => {
+    const { authority, data } = await createList(
+      { exactFit: {} },
+      { append: {} }
+    );
+
+    const entries = Array.from(
+      { length: 25 },
+      () => anchor.web3.Keypair.generate().publicKey
+    );
+    await program.methods
+      .addMany(entries, 0, null)
+      .accounts(listAccounts(data, authority.publicKey))
+      .signers([authority])
+      .rpc();
+
+    const account = await provider.connection.getAccountInfo(data);
+    assert.equal(account.data.length, INIT_SIZE + 25 * ENTRY_SIZE);
+    assert.equal(
+      account.lamports,
+      await provider.connection.getMinimumBalanceForRentExemption(
+        account.data.length
+      )
+    );
+
+    const { list } = await program.account.data.fetch(data);
+    assert.deepEqual(
+      list.map((e) => e.key.toBase58()),
+      entries.map((k) => k.toBase58())
+    );
+  });
+
+  it("Keeps a sorted set and answers contains", async () => {
+    const { authority, data } = await createList(
+      { fixed: { entries: 10 } },
+      { set: {} }
+    );
+
+    const entries = Array.from(
+      { length: 5 },
+      () => anchor.web3.Keypair.generate().publicKey
+    );
+    await program.methods
+      .addMany(entries, 0, null)
+      .accounts(listAccounts(data, authority.publicKey))
+      .signers([authority])
+      .rpc();
+
+    const { list } = await program.account.data.fetch(data);
+    assert.deepEqual(
+      list.map((e) => e.key.toBase58()),
+      [...entries]
+        .sort((a, b) => Buffer.compare(a.toBuffer(), b.toBuffer()))
+        .map((k) => k.toBase58())
+    );
+
+    try {
+      await program.methods
+        .add(entries[2], 0, null)
+        .accounts(listAccounts(data, authority.publicKey))
+        .signers([authority])
+        .rpc();
+      assert.fail("duplicate add should have been rejected");
+    } catch (e) {
+      assert.equal(e.error?.errorCode?.code, "DuplicateEntry");
+    }
+
+    assert.isTrue(
+      await program.methods.contains(entries[3]).accounts({ data }).view()
+    );
+    assert.isFalse(
+      await program.methods
+        .contains(authority.publicKey)
+        .accounts({ data })
+        .view()
+    );
+  });
+
+  it("Spreads a paged list over multiple pages", async () => {
+    const authority = await fundedKeypair();
+    const [list] = PublicKey.findProgramAddressSync(
+      [Buffer.from("paged"), authority.publicKey.toBuffer()],
+      program.programId
+    );
+    const pageOf = (index: number) => {
+      const indexBytes = Buffer.alloc(4);
+      indexBytes.writeUInt32LE(index);
+      return PublicKey.findProgramAddressSync(
+        [Buffer.from("page"), list.toBuffer(), indexBytes],
+        program.programId
+      )[0];
+    };
+
+    await program.methods
+      .initializePaged(2)
+      .accounts({ list, signer: authority.publicKey })
+      .signers([authority])
+      .rpc();
+
+    // anyone can send lamports to a page before it exists, which mustn't
+    // keep it from being created
+    await provider.sendAndConfirm(
+      new anchor.web3.Transaction().add(
+        anchor.web3.SystemProgram.transfer({
+          fromPubkey: wallet.publicKey,
+          toPubkey: pageOf(1),
+          lamports: 1_000,
+        })
+      )
+    );
+
+    const entries = Array.from(
+      { length: 5 },
+      () => anchor.web3.Keypair.generate().publicKey
+    );
+    for (const [i, entry] of entries.entries()) {
+      await program.methods
+        .addPaged(entry)
+        .accounts({
+          list,
+          page: pageOf(Math.floor(i / 2)),
+          signer: authority.publicKey,
+          ...eventAccounts,
+        })
+        .signers([authority])
+        .rpc();
+    }
+
+    const header = await program.account.pagedList.fetch(list);
+    assert.equal(header.pageCount, 3);
+    assert.equal(header.len.toNumber(), 5);
+
+    const stored = [];
+    for (let i = 0; i < header.pageCount; i++) {
+      const page = await program.account.dataPage.fetch(pageOf(i));
+      assert.equal(page.index, i);
+      stored.push(...page.entries);
+    }
+    assert.deepEqual(
+      stored.map((k) => k.toBase58()),
+      entries.map((k) => k.toBase58())
+    );
+
+    // the pages holding the window are passed in order
+    const slice = await program.methods
+      .listPaged(new anchor.BN(1), 3)
+      .accounts({ list })
+      .remainingAccounts(
+        [0, 1].map((i) => ({
+          pubkey: pageOf(i),
+          isSigner: false,
+          isWritable: false,
+        }))
+      )
+      .view();
+    assert.equal(slice.total.toNumber(), 5);
+    assert.deepEqual(
+      slice.entries.map((k) => k.toBase58()),
+      entries.slice(1, 4).map((k) => k.toBase58())
+    );
+  });
+
+  it("Pages through a list via return data", async () => {
+    const { authority, data } = await createList(
+      { fixed: { entries: 10 } },
+      { append: {} }
+    );
+
+    const entries = Array.from(
+      { length: 40 },
+      () => anchor.web3.Keypair.generate().publicKey
+    );
+    for (const batch of [entries.slice(0, 20), entries.slice(20)]) {
+      await program.methods
+        .addMany(batch, 0, null)
+        .accounts(listAccounts(data, authority.publicKey))
+        .signers([authority])
+        .rpc();
+    }
+
+    const listed = [];
+    let total = Infinity;
+    while (listed.length < total) {
+      const slice = await program.methods
+        .list(listed.length, 100)
+        .accounts({ data })
+        .view();
+      total = slice.total;
+      // capped by the return data size
+      assert.isAtMost(slice.entries.length, 11);
+      listed.push(...slice.entries);
+    }
+    assert.deepEqual(
+      listed.map((e) => e.key.toBase58()),
+      entries.map((k) => k.toBase58())
+    );
+  });
+
+  it("Emits events for entry changes and resizes", async () => {
+    const { authority, data } = await createList(
+      { exactFit: {} },
+      { append: {} }
+    );
+
+    const logged = [];
+    const listener = program.addEventListener("EntryAdded", (event) =>
+      logged.push(event)
+    );
+
+    const entry = anchor.web3.Keypair.generate().publicKey;
+    const tx = await program.methods
+      .add(entry, 0, null)
+      .accounts(listAccounts(data, authority.publicKey))
+      .signers([authority])
+      .rpc({ commitment: "confirmed" });
+
+    await new Promise((resolve) => setTimeout(resolve, 1000));
+    await program.removeEventListener(listener);
+    assert.equal(logged.length, 1);
+    assert.equal(logged[0].entry.toBase58(), entry.toBase58());
+
+    // the same events are carried by the self-CPI instructions
+    const txInfo = await provider.connection.getTransaction(tx, {
+      commitment: "confirmed",
+    });
+    const cpiEvents = txInfo.meta.innerInstructions
+      .flatMap((ix) => ix.instructions)
+      .filter(
+        (ix) =>
+          txInfo.transaction.message.accountKeys[ix.programIdIndex].equals(
+            program.programId
+          )
+      )
+      .map((ix) => {
+        const ixData = anchor.utils.bytes.bs58.decode(ix.data);
+        return program.coder.events.decode(
+          anchor.utils.bytes.base64.encode(Buffer.from(ixData.subarray(8)))
+        );
+      });
+
+    assert.deepEqual(
+      cpiEvents.map((e) => e.name),
+      ["AccountResized", "RentToppedUp", "EntryAdded"]
+    );
+    assert.equal(cpiEvents[0].data.oldSize, INIT_SIZE);
+    assert.equal(cpiEvents[0].data.newSize, INIT_SIZE + ENTRY_SIZE);
+    assert.equal(cpiEvents[2].data.index, 0);
+  });
+
+  it("Migrates a v1 account in place", async () => {
+    // loaded into the validator from fixtures/accounts
+    const authority = anchor.web3.Keypair.fromSecretKey(
+      Uint8Array.from(
+        JSON.parse(fs.readFileSync("fixtures/legacy-authority.json", "utf8"))
+      )
+    );
+    await provider.connection.confirmTransaction(
+      await provider.connection.requestAirdrop(
+        authority.publicKey,
+        anchor.web3.LAMPORTS_PER_SOL
+      )
+    );
+    const data = dataPdaOf(authority.publicKey);
+
+    await program.methods
+      .migrate()
+      .accounts({
+        data,
+        directory: directoryPdaOf(authority.publicKey),
+        signer: authority.publicKey,
+        ...eventAccounts,
+      })
+      .signers([authority])
+      .rpc();
+
+    const migrated = await program.account.data.fetch(data);
+    assert.equal(migrated.header.version, 2);
+    assert.equal(
+      migrated.header.authority.toBase58(),
+      authority.publicKey.toBase58()
+    );
+    // sized to the entries it holds
+    assert.equal(migrated.header.capacity, 2);
+    assert.equal(migrated.header.count, 2);
+    // v1 lists grew ten keys at a time
+    assert.deepEqual(migrated.header.growth, { fixed: { entries: 10 } });
+    assert.deepEqual(
+      migrated.list.map((e) => e.key.toBase58()),
+      [
+        "3TDByekaAKMB4gGhHu7m7KptM1BxLkP59jDRUtG8ZmpG",
+        "2kJdKkHyQhPDvtcBWJYhCrKGkvorDhbbdzAyxQkFqUQY",
+      ]
+    );
+
+    assert.equal(
+      migrated.list[0].addedBy.toBase58(),
+      authority.publicKey.toBase58()
+    );
+    assert.equal(migrated.list[0].timestamp.toNumber(), 0);
+
+    const account = await provider.connection.getAccountInfo(data);
+    assert.equal(account.data.length, INIT_SIZE + 2 * ENTRY_SIZE);
+
+    // the unnamed list keeps its address and joins the directory
+    assert.equal(nameOf(migrated.header.name), "");
+    const directory = await program.account.directory.fetch(
+      directoryPdaOf(authority.publicKey)
+    );
+    assert.deepEqual(
+      directory.lists.map((l) => [nameOf(l.name), l.data.toBase58()]),
+      [["", data.toBase58()]]
+    );
+
+    try {
+      await program.methods
+        .migrate()
+        .accounts({
+          data,
+          directory: directoryPdaOf(authority.publicKey),
+          signer: authority.publicKey,
+          ...eventAccounts,
+        })
+        .signers([authority])
+        .rpc();
+      assert.fail("second migrate should have been rejected");
+    } catch (e) {
+      assert.equal(e.error?.errorCode?.code, "AlreadyMigrated");
+    }
+  });
+
+  it("Adds at a flat compute cost from 10 to 10,000 entries", async () => {
+    // filling the list reallocs every 110 entries; `addCost` reserves room
+    // first so that only the append itself is timed
+    const { authority, data } = await createList(
+      { fixed: { entries: 110 } },
+      { append: {} }
+    );
+
+    const fillTo = async (target: number) => {
+      let { count } = (await program.account.data.fetch(data)).header;
+      while (count < target) {
+        const batch = Array.from(
+          { length: Math.min(25, target - count) },
+          () => anchor.web3.Keypair.generate().publicKey
+        );
+        await program.methods
+          .addMany(batch, 0, null)
+          .accounts(listAccounts(data, authority.publicKey))
+          .signers([authority])
+          .rpc();
+        count += batch.length;
+      }
+    };
+
+    const addCost = async () => {
+      const accounts = listAccounts(data, authority.publicKey);
+      await program.methods
+        .reserve(1)
+        .accounts(accounts)
+        .signers([authority])
+        .rpc();
+      const tx = await program.methods
+        .add(anchor.web3.Keypair.generate().publicKey, 0, null)
+        .accounts(accounts)
+        .signers([authority])
+        .rpc({ commitment: "confirmed" });
+      const txInfo = await provider.connection.getTransaction(tx, {
+        commitment: "confirmed",
+      });
+      return txInfo.meta.computeUnitsConsumed;
+    };
+
+    const costs = [];
+    for (const size of [10, 100, 1_000, 10_000]) {
+      await fillTo(size);
+      costs.push(await addCost());
+    }
+    console.log("add compute units at 10/100/1k/10k entries", costs);
+
+    assert.isAtMost(Math.max(...costs) - Math.min(...costs), 500);
+  });
+
+  it("Lets a separate payer fund rent growth", async () => {
+    const { authority, data } = await createList(
+      { exactFit: {} },
+      { append: {} }
+    );
+    const sponsor = await fundedKeypair();
+
+    const authorityBefore = await provider.connection.getBalance(
+      authority.publicKey
+    );
+    const sponsorBefore = await provider.connection.getBalance(
+      sponsor.publicKey
+    );
+    const dataBefore = await provider.connection.getBalance(data);
+
+    await program.methods
+      .add(anchor.web3.Keypair.generate().publicKey, 0, null)
+      .accounts({
+        ...listAccounts(data, authority.publicKey),
+        payer: sponsor.publicKey,
+      })
+      .signers([authority, sponsor])
+      .rpc();
+
+    const topUp = (await provider.connection.getBalance(data)) - dataBefore;
+    assert.isAbove(topUp, 0);
+    assert.equal(
+      await provider.connection.getBalance(authority.publicKey),
+      authorityBefore
+    );
+    assert.equal(
+      await provider.connection.getBalance(sponsor.publicKey),
+      sponsorBefore - topUp
+    );
+    const { header } = await program.account.data.fetch(data);
+    assert.equal(header.sponsor.toBase58(), sponsor.publicKey.toBase58());
+    assert.equal(header.sponsored.toNumber(), topUp);
+
+    // shrinking the list pays the sponsor back, not the authority
+    const [entry] = (await program.account.data.fetch(data)).list;
+    try {
+      await program.methods
+        .remove(entry.key, await proofOf(data, entry.key))
+        .accounts(listAccounts(data, authority.publicKey))
+        .signers([authority])
+        .rpc();
+      assert.fail("shrinking without the sponsor should have been rejected");
+    } catch (e) {
+      assert.equal(e.error?.errorCode?.code, "SponsorMismatch");
+    }
+    await program.methods
+      .remove(entry.key, await proofOf(data, entry.key))
+      .accounts({
+        ...listAccounts(data, authority.publicKey),
+        sponsor: sponsor.publicKey,
+      })
+      .signers([authority])
+      .rpc();
+    assert.equal(
+      await provider.connection.getBalance(sponsor.publicKey),
+      sponsorBefore
+    );
+    assert.equal(
+      await provider.connection.getBalance(authority.publicKey),
+      authorityBefore
+    );
+  });
+
+  it("Closes a list and lets it be initialized again", async () => {
+    const { authority, data } = await createList(
+      { fixed: { entries: 10 } },
+      { append: {} }
+    );
+    const recipient = anchor.web3.Keypair.generate().publicKey;
+    await program.methods
+      .add(anchor.web3.Keypair.generate().publicKey, 0, null)
+      .accounts(listAccounts(data, authority.publicKey))
+      .signers([authority])
+      .rpc();
+
+    const directory = directoryPdaOf(authority.publicKey);
+    const lamports = await provider.connection.getBalance(data);
+    const directoryLamports = await provider.connection.getBalance(directory);
+    const closed = [];
+    const listener = program.addEventListener("ListClosed", (event) =>
+      closed.push(event)
+    );
+    await program.methods
+      .close()
+      .accounts({
+        data,
+        directory,
+        signer: authority.publicKey,
+        recipient,
+        ...eventAccounts,
+      })
+      .signers([authority])
+      .rpc({ commitment: "confirmed" });
+
+    await new Promise((resolve) => setTimeout(resolve, 1000));
+    await program.removeEventListener(listener);
+    assert.equal(closed.length, 1);
+    assert.equal(closed[0].data.toBase58(), data.toBase58());
+    assert.equal(closed[0].recipient.toBase58(), recipient.toBase58());
+    assert.equal(closed[0].lamports.toNumber(), lamports);
+
+    assert.isNull(await provider.connection.getAccountInfo(data));
+    // the rent of the list's directory entry goes back to the authority
+    // instead
+    assert.equal(await provider.connection.getBalance(recipient), lamports);
+    assert.isBelow(
+      await provider.connection.getBalance(directory),
+      directoryLamports
+    );
+    assert.deepEqual(
+      (await program.account.directory.fetch(directory)).lists,
+      []
+    );
+
+    // lamports sent to the closed address don't bring the list back
+    await provider.sendAndConfirm(
+      new anchor.web3.Transaction().add(
+        anchor.web3.SystemProgram.transfer({
+          fromPubkey: wallet.publicKey,
+          toPubkey: data,
+          lamports: anchor.web3.LAMPORTS_PER_SOL / 10,
+        })
+      )
+    );
+    const revived = await provider.connection.getAccountInfo(data);
+    assert.isTrue(revived.owner.equals(anchor.web3.SystemProgram.programId));
+    assert.equal(revived.data.length, 0);
+    try {
+      await program.methods
+        .add(anchor.web3.Keypair.generate().publicKey, 0, null)
+        .accounts(listAccounts(data, authority.publicKey))
+        .signers([authority])
+        .rpc();
+      assert.fail("add to a closed list should have been rejected");
+    } catch (e) {
+      assert.equal(e.error?.errorCode?.code, "AccountOwnedByWrongProgram");
+    }
+
+    await createList({ fixed: { entries: 10 } }, { append: {} }, "", authority);
+    const { list } = await program.account.data.fetch(data);
+    assert.equal(list.length, 0);
+  });
+
+  it("Reserves capacity ahead of a burst and shrinks to fit", async () => {
+    const { authority, data } = await createList(
+      { exactFit: {} },
+      { append: {} }
+    );
+
+    const accounts = listAccounts(data, authority.publicKey);
+
+    await program.methods
+      .reserve(50)
+      .accounts(accounts)
+      .signers([authority])
+      .rpc();
+    let account = await provider.connection.getAccountInfo(data);
+    assert.equal(account.data.length, INIT_SIZE + 50 * ENTRY_SIZE);
+    assert.equal((await program.account.data.fetch(data)).header.capacity, 50);
+
+    await program.methods
+      .add(anchor.web3.Keypair.generate().publicKey, 0, null)
+      .accounts(accounts)
+      .signers([authority])
+      .rpc();
+    account = await provider.connection.getAccountInfo(data);
+    assert.equal(account.data.length, INIT_SIZE + 50 * ENTRY_SIZE);
+
+    await program.methods
+      .shrinkToFit()
+      .accounts(accounts)
+      .signers([authority])
+      .rpc();
+    account = await provider.connection.getAccountInfo(data);
+    assert.equal(account.data.length, INIT_SIZE + ENTRY_SIZE);
+    assert.equal(
+      account.lamports,
+      await provider.connection.getMinimumBalanceForRentExemption(
+        account.data.length
+      )
+    );
+    const { capacity, count } = (await program.account.data.fetch(data)).header;
+    assert.equal(capacity, 1);
+    assert.equal(count, 1);
+
+    try {
+      await program.methods
+        .reserve(1_000)
+        .accounts(accounts)
+        .signers([authority])
+        .rpc();
+      assert.fail("reserve past the realloc limit should have been rejected");
+    } catch (e) {
+      assert.equal(e.error?.errorCode?.code, "ReallocLimitExceeded");
+    }
+  });
+
+  it("Ring buffer keeps the newest entries", async () => {
+    const { authority, data } = await createList(
+      { fixed: { entries: 2 } },
+      { ring: { capacity: 3 } }
+    );
+
+    const entries = [...Array(5)].map(
+      () => anchor.web3.Keypair.generate().publicKey
+    );
+    for (const entry of entries) {
+      await program.methods
+        .add(entry, 0, null)
+        .accounts(listAccounts(data, authority.publicKey))
+        .signers([authority])
+        .rpc();
+    }
+
+    // growth stops at the ring capacity
+    const account = await provider.connection.getAccountInfo(data);
+    assert.equal(account.data.length, INIT_SIZE + 3 * ENTRY_SIZE);
+    const { count, head, tail } = (
+      await program.account.data.fetch(data)
+    ).header;
+    assert.equal(count, 3);
+    assert.equal(head, 2);
+    assert.equal(tail, 2);
+
+    const slice = await program.methods
+      .list(0, 10)
+      .accounts({ data })
+      .view();
+    assert.equal(slice.total, 3);
+    assert.deepEqual(
+      slice.entries.map((e) => e.key.toBase58()),
+      entries.slice(2).map((k) => k.toBase58())
+    );
+
+    // removing unrolls the ring into insertion order. The evicted keys
+    // still hold the first leaves, which only the caller knows
+    await program.methods
+      .remove(entries[3], merkleProof(entries.map(merkleLeaf), 3).proof)
+      .accounts(listAccounts(data, authority.publicKey))
+      .signers([authority])
+      .rpc();
+    const after = await program.account.data.fetch(data);
+    assert.equal(after.header.head, 0);
+    assert.equal(after.header.tail, 2);
+    assert.deepEqual(
+      after.list.map((e) => e.key.toBase58()),
+      [entries[2], entries[4]].map((k) => k.toBase58())
+    );
+  });
+
+  it("Rejects a ring without capacity", async () => {
+    try {
+      await createList({ exactFit: {} }, { ring: { capacity: 0 } });
+      assert.fail("a ring of capacity 0 should have been rejected");
+    } catch (e) {
+      assert.equal(e.error?.errorCode?.code, "InvalidRingCapacity");
+    }
+  });
+
+  it("Prunes expired entries and rewards the caller", async () => {
+    const { authority, data } = await createList(
+      { exactFit: {} },
+      { append: {} }
+    );
+    await program.methods
+      .setPruneReward(5_000)
+      .accounts({ data, signer: authority.publicKey, ...eventAccounts })
+      .signers([authority])
+      .rpc();
+
+    const blockTime = async () =>
+      provider.connection.getBlockTime(await provider.connection.getSlot());
+    const expiresAt = (await blockTime()) + 2;
+
+    const accounts = listAccounts(data, authority.publicKey);
+    const [a, b, c] = [...Array(3)].map(
+      () => anchor.web3.Keypair.generate().publicKey
+    );
+    await program.methods
+      .addMany([a, b], 0, new anchor.BN(expiresAt))
+      .accounts(accounts)
+      .signers([authority])
+      .rpc();
+    await program.methods
+      .add(c, 0, null)
+      .accounts(accounts)
+      .signers([authority])
+      .rpc();
+    assert.isTrue(await program.methods.contains(a).accounts({ data }).view());
+
+    while ((await blockTime()) < expiresAt) {
+      await new Promise((resolve) => setTimeout(resolve, 500));
+    }
+    assert.isFalse(await program.methods.contains(a).accounts({ data }).view());
+
+    const caller = await fundedKeypair();
+    const balances = async () =>
+      Promise.all(
+        [data, authority.publicKey, caller.publicKey].map((key) =>
+          provider.connection.getBalance(key)
+        )
+      );
+    // a and b hold the first two leaves, and each proof is taken with the
+    // leaves before it emptied
+    const leaves = await merkleLeaves(data);
+    const proofs = [0, 1].map((leaf) => {
+      const { proof } = merkleProof(leaves, leaf);
+      leaves[leaf] = Buffer.alloc(32);
+      return proof;
+    });
+    const before = await balances();
+    await program.methods
+      .pruneExpired(proofs)
+      .accounts({
+        data,
+        authority: authority.publicKey,
+        caller: caller.publicKey,
+        ...eventAccounts,
+      })
+      .signers([caller])
+      .rpc();
+    const after = await balances();
+
+    const { list } = await program.account.data.fetch(data);
+    assert.deepEqual(list.map((e) => e.key.toBase58()), [c.toBase58()]);
+    const account = await provider.connection.getAccountInfo(data);
+    assert.equal(account.data.length, INIT_SIZE + ENTRY_SIZE);
+
+    const reclaimed = before[0] - after[0];
+    const reward = Math.floor(reclaimed / 2);
+    assert.isAbove(reclaimed, 0);
+    assert.equal(after[2] - before[2], reward);
+    assert.equal(after[1] - before[1], reclaimed - reward);
+
+    try {
+      await program.methods
+        .pruneExpired([])
+        .accounts({
+          data,
+          authority: authority.publicKey,
+          caller: caller.publicKey,
+          ...eventAccounts,
+        })
+        .signers([caller])
+        .rpc();
+      assert.fail("prune without expired entries should have been rejected");
+    } catch (e) {
+      assert.equal(e.error?.errorCode?.code, "NothingToPrune");
+    }
+
+    try {
+      await program.methods
+        .add(a, 0, new anchor.BN(expiresAt))
+        .accounts(accounts)
+        .signers([authority])
+        .rpc();
+      assert.fail("an entry that is already expired should have been rejected");
+    } catch (e) {
+      assert.equal(e.error?.errorCode?.code, "InvalidExpiry");
+    }
+  });
+
+  it("Keeps a merkle root of the list and checks proofs", async () => {
+    const { authority, data } = await createList(
+      { fixed: { entries: 10 } },
+      { set: {} }
+    );
+
+    const accounts = listAccounts(data, authority.publicKey);
+    const entries = Array.from(
+      { length: 5 },
+      () => anchor.web3.Keypair.generate().publicKey
+    );
+    await program.methods
+      .addMany(entries.slice(0, 3), 0, null)
+      .accounts(accounts)
+      .signers([authority])
+      .rpc();
+    for (const entry of entries.slice(3)) {
+      await program.methods
+        .add(entry, 0, null)
+        .accounts(accounts)
+        .signers([authority])
+        .rpc();
+    }
+
+    const rootOf = async () =>
+      Buffer.from((await program.account.data.fetch(data)).header.merkle.root);
+
+    // the set is sorted, the leaves stay in the order the keys came in
+    const leaves = entries.map(merkleLeaf);
+    assert.deepEqual(await merkleLeaves(data), leaves);
+    assert.deepEqual(await rootOf(), merkleProof(leaves, 0).root);
+
+    const proof = await proofOf(data, entries[2]);
+    await program.methods
+      .verifyMembership(entries[2], proof)
+      .accounts({ data })
+      .rpc();
+
+    try {
+      await program.methods
+        .verifyMembership(entries[1], proof)
+        .accounts({ data })
+        .rpc();
+      assert.fail("a proof for another entry should have been rejected");
+    } catch (e) {
+      assert.equal(e.error?.errorCode?.code, "InvalidProof");
+    }
+
+    // removing empties the leaf, the others can still be proven
+    await program.methods
+      .remove(entries[0], await proofOf(data, entries[0]))
+      .accounts(accounts)
+      .signers([authority])
+      .rpc();
+    leaves[0] = Buffer.alloc(32);
+    assert.deepEqual(await rootOf(), merkleProof(leaves, 0).root);
+    await program.methods
+      .verifyMembership(entries[4], await proofOf(data, entries[4]))
+      .accounts({ data })
+      .rpc();
+  });
+
+  it("Keeps a sorted map with the same growth and refunds", async () => {
+    const authority = await fundedKeypair();
+    const [map] = PublicKey.findProgramAddressSync(
+      [Buffer.from("map"), authority.publicKey.toBuffer()],
+      program.programId
+    );
+    await program.methods
+      .initializeMap({ exactFit: {} })
+      .accounts({ map, signer: authority.publicKey })
+      .signers([authority])
+      .rpc();
+
+    // discriminator + authority + bump + growth policy + vec length
+    const MAP_INIT_SIZE = 8 + 32 + 1 + 3 + 4;
+    // key + value
+    const MAP_ENTRY_SIZE = 32 + 8;
+    const accounts = { map, signer: authority.publicKey, ...eventAccounts };
+    const [a, b] = [...Array(2)].map(
+      () => anchor.web3.Keypair.generate().publicKey
+    );
+    const get = async (key: PublicKey) =>
+      program.methods.get(key).accounts({ map }).view();
+
+    await program.methods
+      .upsert(a, new anchor.BN(10))
+      .accounts(accounts)
+      .signers([authority])
+      .rpc();
+    await program.methods
+      .increment(b, new anchor.BN(5))
+      .accounts(accounts)
+      .signers([authority])
+      .rpc();
+    await program.methods
+      .increment(a, new anchor.BN(7))
+      .accounts(accounts)
+      .signers([authority])
+      .rpc();
+
+    assert.equal((await get(a)).toNumber(), 17);
+    assert.equal((await get(b)).toNumber(), 5);
+    const { entries } = await program.account.map.fetch(map);
+    assert.deepEqual(
+      entries.map((e) => e.key.toBase58()),
+      [a, b]
+        .sort((x, y) => Buffer.compare(x.toBuffer(), y.toBuffer()))
+        .map((k) => k.toBase58())
+    );
+    let account = await provider.connection.getAccountInfo(map);
+    assert.equal(account.data.length, MAP_INIT_SIZE + 2 * MAP_ENTRY_SIZE);
+
+    try {
+      await program.methods
+        .increment(a, new anchor.BN("18446744073709551615"))
+        .accounts(accounts)
+        .signers([authority])
+        .rpc();
+      assert.fail("an overflowing increment should have been rejected");
+    } catch (e) {
+      assert.equal(e.error?.errorCode?.code, "ValueOverflow");
+    }
+
+    await program.methods
+      .delete(a)
+      .accounts(accounts)
+      .signers([authority])
+      .rpc();
+    assert.isNull(await get(a));
+    account = await provider.connection.getAccountInfo(map);
+    assert.equal(account.data.length, MAP_INIT_SIZE + MAP_ENTRY_SIZE);
+    assert.equal(
+      account.lamports,
+      await provider.connection.getMinimumBalanceForRentExemption(
+        account.data.length
+      )
+    );
+  });
+
+  it("Transfers authority in two steps and seals a list", async () => {
+    const { authority, data } = await createList(
+      { fixed: { entries: 10 } },
+      { append: {} }
+    );
+    const successor = await fundedKeypair();
+
+    const add = (signer: anchor.web3.Keypair) =>
+      program.methods
+        .add(anchor.web3.Keypair.generate().publicKey, 0, null)
+        .accounts(listAccounts(data, signer.publicKey))
+        .signers([signer])
+        .rpc();
+
+    await program.methods
+      .transferAuthority(successor.publicKey)
+      .accounts({ data, signer: authority.publicKey, ...eventAccounts })
+      .signers([authority])
+      .rpc();
+    // the current authority keeps control until the transfer is accepted
+    await add(authority);
+    await program.methods
+      .acceptAuthority()
+      .accounts({
+        data,
+        previousDirectory: directoryPdaOf(authority.publicKey),
+        previousAuthority: authority.publicKey,
+        directory: directoryPdaOf(successor.publicKey),
+        signer: successor.publicKey,
+        ...eventAccounts,
+      })
+      .signers([successor])
+      .rpc();
+
+    let list = await program.account.data.fetch(data);
+    assert.equal(
+      list.header.authority.toBase58(),
+      successor.publicKey.toBase58()
+    );
+    assert.equal(
+      list.header.creator.toBase58(),
+      authority.publicKey.toBase58()
+    );
+    assert.equal(
+      list.header.pendingAuthority.toBase58(),
+      PublicKey.default.toBase58()
+    );
+    // the list moves to the successor's directory
+    assert.deepEqual(
+      (
+        await program.account.directory.fetch(
+          directoryPdaOf(authority.publicKey)
+        )
+      ).lists,
+      []
+    );
+    assert.equal(
+      (
+        await program.account.directory.fetch(
+          directoryPdaOf(successor.publicKey)
+        )
+      ).lists[0].data.toBase58(),
+      data.toBase58()
+    );
+
+    try {
+      await add(authority);
+      assert.fail("the previous authority should have been rejected");
+    } catch (e) {
+      assert.equal(e.error?.errorCode?.code, "Unauthorized");
+    }
+    await add(successor);
+
+    await program.methods
+      .seal()
+      .accounts({ data, signer: successor.publicKey, ...eventAccounts })
+      .signers([successor])
+      .rpc();
+    list = await program.account.data.fetch(data);
+    assert.isTrue(list.header.sealed);
+
+    try {
+      await add(successor);
+      assert.fail("add to a sealed list should have been rejected");
+    } catch (e) {
+      assert.equal(e.error?.errorCode?.code, "ListSealed");
+    }
+    try {
+      await program.methods
+        .remove(list.list[0].key, [])
+        .accounts(listAccounts(data, successor.publicKey))
+        .signers([successor])
+        .rpc();
+      assert.fail("remove from a sealed list should have been rejected");
+    } catch (e) {
+      assert.equal(e.error?.errorCode?.code, "ListSealed");
+    }
+  });
+
+  it("Keeps several named lists per wallet in a directory", async () => {
+    const operator = await fundedKeypair();
+    const directory = directoryPdaOf(operator.publicKey);
+    const names = ["allowlist", "blocklist", "partners"];
+    for (const name of names) {
+      await createList({ exactFit: {} }, { set: {} }, name, operator);
+    }
+
+    const blocklist = dataPdaOf(operator.publicKey, "blocklist");
+    const blocked = anchor.web3.Keypair.generate().publicKey;
+    await program.methods
+      .add(blocked, 0, null)
+      .accounts(listAccounts(blocklist, operator.publicKey))
+      .signers([operator])
+      .rpc();
+
+    for (const name of names) {
+      const list = await program.account.data.fetch(
+        dataPdaOf(operator.publicKey, name)
+      );
+      assert.equal(nameOf(list.header.name), name);
+      assert.equal(list.list.length, name == "blocklist" ? 1 : 0);
+    }
+
+    const { lists } = await program.account.directory.fetch(directory);
+    assert.deepEqual(
+      lists.map((l) => [nameOf(l.name), l.data.toBase58()]),
+      names.map((name) => [
+        name,
+        dataPdaOf(operator.publicKey, name).toBase58(),
+      ])
+    );
+
+    try {
+      await createList({ exactFit: {} }, { set: {} }, "part\0ners", operator);
+      assert.fail("a name with a NUL byte should have been rejected");
+    } catch (e) {
+      assert.equal(e.error?.errorCode?.code, "InvalidName");
+    }
+  });
+
+  it("Copies and merges a list in chunks", async () => {
+    const { authority: owner, data: source } = await createList(
+      { exactFit: {} },
+      { append: {} },
+      "source"
+    );
+    const { data: fork } = await createList(
+      { exactFit: {} },
+      { append: {} },
+      "fork",
+      owner
+    );
+
+    const keys = [0, 1, 2].map(() => anchor.web3.Keypair.generate().publicKey);
+    await program.methods
+      .addMany(keys, 0, null)
+      .accounts(listAccounts(source, owner.publicKey))
+      .signers([owner])
+      .rpc();
+
+    const copyAccounts = { ...listAccounts(fork, owner.publicKey), source };
+
+    const added = [];
+    const copied = [];
+    const listeners = [
+      program.addEventListener("EntryAdded", (event) => added.push(event)),
+      program.addEventListener("EntriesCopied", (event) => copied.push(event)),
+    ];
+
+    // two entries per call, resuming from the cursor the first call returned
+    for (const method of ["copyFrom", "mergeFrom"] as const) {
+      for (const cursor of [0, 2]) {
+        await program.methods[method](cursor, 2)
+          .accounts(copyAccounts)
+          .signers([owner])
+          .rpc({ commitment: "confirmed" });
+      }
+    }
+
+    await new Promise((resolve) => setTimeout(resolve, 1000));
+    for (const listener of listeners) {
+      await program.removeEventListener(listener);
+    }
+    // every copied entry is announced on its own, and the merge says which
+    // keys it skipped
+    const toBase58 = (keys: PublicKey[]) => keys.map((k) => k.toBase58());
+    assert.deepEqual(
+      toBase58(added.filter((e) => e.data.equals(fork)).map((e) => e.entry)),
+      toBase58(keys)
+    );
+    assert.deepEqual(
+      copied.map((e) => toBase58(e.skipped)),
+      [[], [], toBase58(keys.slice(0, 2)), toBase58(keys.slice(2))]
+    );
+
+    // a chunk has to continue the copy in progress, from an unchanged source
+    await program.methods
+      .copyFrom(0, 2)
+      .accounts(copyAccounts)
+      .signers([owner])
+      .rpc();
+    await program.methods
+      .add(anchor.web3.Keypair.generate().publicKey, 0, null)
+      .accounts(listAccounts(source, owner.publicKey))
+      .signers([owner])
+      .rpc();
+    try {
+      await program.methods
+        .copyFrom(2, 2)
+        .accounts(copyAccounts)
+        .signers([owner])
+        .rpc();
+      assert.fail("a changed source should have been rejected");
+    } catch (e) {
+      assert.equal(e.error?.errorCode?.code, "SourceChanged");
+    }
+    try {
+      await program.methods
+        .copyFrom(1, 2)
+        .accounts(copyAccounts)
+        .signers([owner])
+        .rpc();
+      assert.fail("a cursor out of step should have been rejected");
+    } catch (e) {
+      assert.equal(e.error?.errorCode?.code, "CursorMismatch");
+    }
+    await program.methods
+      .copyFrom(0, 3)
+      .accounts(copyAccounts)
+      .signers([owner])
+      .rpc();
+
+    try {
+      await program.methods
+        .mergeFrom(0, 0)
+        .accounts(copyAccounts)
+        .signers([owner])
+        .rpc();
+      assert.fail("a limit of zero should have been rejected");
+    } catch (e) {
+      assert.equal(e.error?.errorCode?.code, "InvalidLimit");
+    }
+
+    // the copy was started over, and is left half done
+    const list = await program.account.data.fetch(fork);
+    assert.deepEqual(
+      list.list.map((e) => e.key.toBase58()),
+      keys.map((k) => k.toBase58())
+    );
+    assert.equal(list.header.copy.cursor, 3);
   });
 });